@@ -41,6 +41,7 @@
             (craneLib.fileset.commonCargoSources unfilteredRoot)
             ./migrations
             ./.sqlx
+            ./config.schema.json
           ];
         };
 
@@ -57,6 +58,8 @@
         commonArgs = {
           inherit src buildInputs nativeBuildInputs;
           strictDeps = true;
+          # Queries are checked against the prepared `.sqlx` data instead of a live database
+          SQLX_OFFLINE = "true";
         };
         cargoArtifacts = craneLib.buildDepsOnly commonArgs;
 
@@ -81,6 +84,21 @@
       {
         checks = {
           inherit bin;
+
+          clippy = craneLib.cargoClippy (
+            commonArgs
+            // {
+              inherit cargoArtifacts;
+              cargoClippyExtraArgs = "--all-targets -- --deny warnings";
+            }
+          );
+
+          tests = craneLib.cargoTest (
+            commonArgs
+            // {
+              inherit cargoArtifacts;
+            }
+          );
         };
 
         packages = {
@@ -0,0 +1,20 @@
+-- Config values edited at runtime, layered over config.toml
+
+CREATE TABLE IF NOT EXISTS config_override (
+    scope VARCHAR(100) NOT NULL,
+    setting VARCHAR(100) NOT NULL,
+    value TEXT NOT NULL,
+    updated_by VARCHAR(19) NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (scope, setting)
+);
+
+CREATE TABLE IF NOT EXISTS config_audit (
+    id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    scope VARCHAR(100) NOT NULL,
+    setting VARCHAR(100) NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    changed_by VARCHAR(19) NOT NULL,
+    changed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
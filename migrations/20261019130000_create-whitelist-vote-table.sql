@@ -0,0 +1,10 @@
+-- Votes cast by reviewers on whitelist requests that need more than one decision
+
+CREATE TABLE IF NOT EXISTS whitelist_vote (
+    request_id VARCHAR(36) NOT NULL,
+    reviewer_id VARCHAR(19) NOT NULL,
+    vote VARCHAR(7) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (request_id, reviewer_id),
+    FOREIGN KEY (request_id) REFERENCES whitelist_request (id) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- Keep decided requests around instead of deleting them, so they can be looked up later
+
+ALTER TABLE whitelist_request
+    ADD COLUMN guild_id VARCHAR(19),
+    ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    ADD COLUMN decided_by VARCHAR(19),
+    ADD COLUMN decided_at TIMESTAMP NULL,
+    ADD COLUMN reason TEXT;
@@ -0,0 +1,5 @@
+-- Where the staff message of each request was posted, so it can be linked to and updated later
+
+ALTER TABLE whitelist_request
+    ADD COLUMN channel_id VARCHAR(19),
+    ADD COLUMN message_id VARCHAR(19);
@@ -0,0 +1,10 @@
+-- Answers to a server's application form, stored alongside the request they were given for
+
+CREATE TABLE IF NOT EXISTS whitelist_answer (
+    request_id VARCHAR(36) NOT NULL,
+    position INT NOT NULL,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    PRIMARY KEY (request_id, position),
+    FOREIGN KEY (request_id) REFERENCES whitelist_request (id) ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+-- Temporary access: when an approval runs out, when the player was warned about it, and when it
+-- was revoked
+
+ALTER TABLE whitelist_request
+    ADD COLUMN expires_at TIMESTAMP NULL,
+    ADD COLUMN expiry_warned_at TIMESTAMP NULL,
+    ADD COLUMN revoked_at TIMESTAMP NULL;
+
+-- How long a reviewer approved access for, the shortest of which applies once a request is
+-- approved
+ALTER TABLE whitelist_vote
+    ADD COLUMN duration_minutes BIGINT NULL;
@@ -0,0 +1,297 @@
+use std::{sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use chrono::{TimeDelta, Utc};
+use poise::serenity_prelude::{ChannelId, CreateEmbed, CreateMessage, Http, User, UserId};
+use sqlx::{MySql, Pool};
+use tracing::{error, info, warn};
+
+use crate::{
+    Error, actions,
+    models::{config::Config, database::WhitelistRequest},
+    requests,
+};
+
+/// How often temporary access is checked.
+const ACCESS_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long before temporary access runs out players are warned, unless the access was granted
+/// for less than four times as long.
+const EXPIRY_WARNING: TimeDelta = TimeDelta::hours(24);
+
+/// Removes players from the whitelist once their temporary access runs out, warning them by DM
+/// beforehand, and retries revocations whose server couldn't be reached.
+pub fn spawn_access_worker(http: Arc<Http>, config: Arc<ArcSwap<Config>>, db: Pool<MySql>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACCESS_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let config = config.load_full();
+            if let Err(err) = process_access(&http, &config, &db).await {
+                error!("Failed to process temporary access: {err}");
+            }
+            if let Err(err) = retry_revocations(&config, &db).await {
+                error!("Failed to retry revoking whitelist access: {err}");
+            }
+        }
+    });
+}
+
+async fn send_dm(http: &Http, request: &WhitelistRequest, embed: CreateEmbed) {
+    let Ok(user_id) = request.discord_id.parse::<u64>() else {
+        return;
+    };
+    let guild_id = request.guild_id.as_deref().and_then(|id| id.parse().ok());
+    let embed = embed.footer(requests::fetch_dm_footer(http, guild_id).await);
+
+    if let Err(error) = UserId::new(user_id)
+        .dm(http, CreateMessage::new().add_embed(embed))
+        .await
+    {
+        error!("Error sending DM: {:?}", error);
+    }
+}
+
+async fn process_access(http: &Http, config: &Config, db: &Pool<MySql>) -> Result<(), Error> {
+    let now = Utc::now();
+    let temporary = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE status = ? AND expires_at IS NOT NULL",
+        WhitelistRequest::APPROVED
+    )
+    .fetch_all(db)
+    .await?;
+
+    for request in temporary {
+        let Some(expires_at) = request.expires_at else {
+            continue;
+        };
+        let Some(server) = config.servers.iter().find(|s| s.id == request.server_id) else {
+            continue;
+        };
+
+        if now < expires_at {
+            let granted_for = expires_at - request.decided_at.unwrap_or(expires_at);
+            let warning = EXPIRY_WARNING.min(granted_for / 4);
+            if request.expiry_warned_at.is_some() || now < expires_at - warning {
+                continue;
+            }
+
+            send_dm(
+                http,
+                &request,
+                CreateEmbed::new()
+                    .title("⏳ Your whitelist access is ending soon")
+                    .description(format!(
+                        "Your access to the server _**{}**_ ends <t:{}:R>.\n\n**Server ID:** `{}`\n**Minecraft Username:** `{}`",
+                        server.name,
+                        expires_at.timestamp(),
+                        server.id,
+                        request.minecraft_username
+                    ))
+                    .color(0xdf8e1d),
+            )
+            .await;
+            sqlx::query!(
+                "UPDATE whitelist_request SET expiry_warned_at = CURRENT_TIMESTAMP WHERE id = ?",
+                request.id
+            )
+            .execute(db)
+            .await?;
+
+            continue;
+        }
+
+        // Retried on the next run if the server can't be reached
+        let kick_message = server
+            .kick_on_expiry()
+            .then_some("Your temporary access has ended");
+        if let Err(err) =
+            requests::unwhitelist_player(config, server, &request.minecraft_username, kick_message)
+                .await
+        {
+            error!(
+                "Failed to remove `{}` from `{}` after their access ran out: {err}",
+                request.minecraft_username, server.id
+            );
+            continue;
+        }
+
+        requests::revoke(db, &request.id, "Temporary access ran out").await?;
+        let guild_id = request.guild_id.as_deref().and_then(|id| id.parse().ok());
+        actions::undo_on_approve(
+            http,
+            db,
+            server,
+            guild_id,
+            &request.discord_id,
+            &request.minecraft_username,
+        )
+        .await;
+        info!(
+            "Removed `{}` from `{}` since the temporary access of request `{}` ran out",
+            request.minecraft_username, server.id, request.id
+        );
+
+        send_dm(
+            http,
+            &request,
+            CreateEmbed::new()
+                .title("🔒 Your whitelist access has ended")
+                .description(format!(
+                    "Your temporary access to the server _**{}**_ has ended and you've been removed from its whitelist.\n\n**Server ID:** `{}`\n**Minecraft Username:** `{}`",
+                    server.name, server.id, request.minecraft_username
+                ))
+                .color(0x8c8fa1),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Removes players whose access was revoked while their server couldn't be reached.
+async fn retry_revocations(config: &Config, db: &Pool<MySql>) -> Result<(), Error> {
+    let revoking = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE status = ?",
+        WhitelistRequest::REVOKING
+    )
+    .fetch_all(db)
+    .await?;
+
+    for request in revoking {
+        let Some(server) = config.servers.iter().find(|s| s.id == request.server_id) else {
+            continue;
+        };
+
+        // Retried on the next run if the server still can't be reached
+        if let Err(err) =
+            requests::unwhitelist_player(config, server, &request.minecraft_username, None).await
+        {
+            warn!(
+                "Failed to remove `{}` from `{}` for revoked request `{}`: {err}",
+                request.minecraft_username, server.id, request.id
+            );
+            continue;
+        }
+
+        let reason = request.reason.as_deref().unwrap_or("Access was revoked");
+        requests::revoke(db, &request.id, reason).await?;
+        info!(
+            "Removed `{}` from `{}` for revoked request `{}`",
+            request.minecraft_username, server.id, request.id
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes every account the user was approved for through the guild from the whitelists, if
+/// the guild has `revoke_on_leave` enabled, and logs each revocation for staff.
+pub async fn revoke_member(
+    http: &Http,
+    config: &Config,
+    db: &Pool<MySql>,
+    guild_id: u64,
+    user: &User,
+    reason: &str,
+) -> Result<(), Error> {
+    let Some(whitelist) = config.whitelist_for(Some(guild_id)) else {
+        return Ok(());
+    };
+    if !whitelist.revoke_on_leave {
+        return Ok(());
+    }
+
+    let discord_id = user.id.to_string();
+    let approved = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE discord_id = ? AND status IN (?, ?)",
+        discord_id,
+        WhitelistRequest::APPROVED,
+        WhitelistRequest::QUEUED
+    )
+    .fetch_all(db)
+    .await?;
+
+    let approved = approved
+        .into_iter()
+        .filter(|r| requests::made_in_guild(config, r, guild_id));
+
+    for request in approved {
+        let Some(server) = config.servers.iter().find(|s| s.id == request.server_id) else {
+            continue;
+        };
+
+        // Leaving by ban also fires a member removal, so only one of them handles each request.
+        // Queued players haven't been whitelisted yet and are revoked right away.
+        let is_whitelisted = request.status == WhitelistRequest::APPROVED;
+        let claimed = match is_whitelisted {
+            true => requests::begin_revoke(db, &request.id, reason).await?,
+            false => requests::revoke(db, &request.id, reason).await?,
+        };
+        if !claimed {
+            continue;
+        }
+
+        let removed = match is_whitelisted {
+            true => {
+                requests::unwhitelist_player(config, server, &request.minecraft_username, None)
+                    .await
+            }
+            false => Ok(()),
+        };
+
+        let embed = match removed {
+            Ok(()) => {
+                if is_whitelisted {
+                    requests::revoke(db, &request.id, reason).await?;
+                }
+                info!(
+                    "Removed `{}` from `{}` since {} {reason}",
+                    request.minecraft_username, server.id, user.name
+                );
+
+                CreateEmbed::new()
+                    .title("🔒 Whitelist access revoked")
+                    .color(0x8c8fa1)
+                    .description(format!(
+                        "<@{}> (`{}`) {reason}, so `{}` was removed from the whitelist.\n\n**Request ID:** {}\n**Server ID:** {}",
+                        discord_id, user.name, request.minecraft_username, request.link(), server.id
+                    ))
+            }
+            Err(err) => {
+                error!(
+                    "Failed to remove `{}` from `{}` after {} {reason}: {err}",
+                    request.minecraft_username, server.id, user.name
+                );
+
+                CreateEmbed::new()
+                    .title("⚠️ Failed to revoke whitelist access")
+                    .color(0xd20f39)
+                    .description(format!(
+                        "<@{}> (`{}`) {reason}, but `{}` couldn't be removed from the whitelist of server `{}` yet. It will be retried every few minutes.\n\n**Request ID:** {}",
+                        discord_id, user.name, request.minecraft_username, server.id, request.link()
+                    ))
+            }
+        };
+
+        let channel = whitelist
+            .log_channel
+            .unwrap_or_else(|| server.request_channel(whitelist));
+        if let Err(err) = ChannelId::new(channel)
+            .send_message(http, CreateMessage::new().add_embed(embed))
+            .await
+        {
+            warn!(
+                "Failed to log the revocation of request `{}`: {err}",
+                request.id
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use poise::serenity_prelude::{
+    ChannelId, CreateMessage, EditMember, GuildId, Http, Member, RoleId, UserId,
+};
+use sqlx::{MySql, Pool};
+use tracing::{info, warn};
+
+use crate::{
+    Error,
+    models::{
+        config::{OnApproveConfig, Server},
+        database::WhitelistRequest,
+    },
+};
+
+/// Longest nickname Discord allows.
+const MAX_NICKNAME_LENGTH: usize = 32;
+
+/// Fills in the placeholders of an `on_approve` template.
+fn render(template: &str, server: &Server, member: &Member, minecraft_username: &str) -> String {
+    template
+        .replace("{minecraft_username}", minecraft_username)
+        .replace("{discord_name}", member.user.display_name())
+        .replace("{server_name}", &server.name)
+        .replace("{server_id}", &server.id)
+        .replace("{address}", server.public_address())
+        .replace("{mention}", &format!("<@{}>", member.user.id))
+}
+
+fn render_nickname(template: &str, server: &Server, member: &Member, username: &str) -> String {
+    render(template, server, member, username)
+        .chars()
+        .take(MAX_NICKNAME_LENGTH)
+        .collect()
+}
+
+async fn fetch_member(
+    http: &Http,
+    guild_id: Option<u64>,
+    discord_id: &str,
+) -> Result<Option<Member>, Error> {
+    let (Some(guild_id), Ok(user_id)) = (guild_id, discord_id.parse::<u64>()) else {
+        return Ok(None);
+    };
+
+    Ok(Some(
+        GuildId::new(guild_id)
+            .member(http, UserId::new(user_id))
+            .await?,
+    ))
+}
+
+async fn apply(
+    http: &Http,
+    on_approve: &OnApproveConfig,
+    server: &Server,
+    member: &Member,
+    minecraft_username: &str,
+) -> Result<(), Error> {
+    for role_id in &on_approve.roles {
+        member
+            .add_role(http, RoleId::new(*role_id))
+            .await
+            .map_err(|err| format!("Failed to give role `{role_id}`: {err}"))?;
+    }
+
+    if let Some(template) = &on_approve.nickname {
+        let nickname = render_nickname(template, server, member, minecraft_username);
+        member
+            .guild_id
+            .edit_member(http, member.user.id, EditMember::new().nickname(nickname))
+            .await
+            .map_err(|err| format!("Failed to set the nickname: {err}"))?;
+    }
+
+    if let Some(channel) = on_approve.welcome_channel {
+        let message = on_approve
+            .welcome_message
+            .as_deref()
+            .unwrap_or(OnApproveConfig::DEFAULT_WELCOME_MESSAGE);
+        ChannelId::new(channel)
+            .send_message(
+                http,
+                CreateMessage::new().content(render(message, server, member, minecraft_username)),
+            )
+            .await
+            .map_err(|err| format!("Failed to post the welcome message: {err}"))?;
+    }
+
+    Ok(())
+}
+
+/// Runs the server's `on_approve` actions for a newly whitelisted player. Failures are only
+/// logged, since the player is whitelisted either way.
+pub async fn run_on_approve(
+    http: &Http,
+    server: &Server,
+    guild_id: Option<u64>,
+    discord_id: &str,
+    minecraft_username: &str,
+) {
+    let Some(on_approve) = server.on_approve() else {
+        return;
+    };
+
+    let member = match fetch_member(http, guild_id, discord_id).await {
+        Ok(Some(member)) => member,
+        Ok(None) => return,
+        Err(err) => {
+            warn!(
+                "Failed to run the approval actions of `{}` for `{discord_id}`: {err}",
+                server.id
+            );
+            return;
+        }
+    };
+
+    match apply(http, on_approve, server, &member, minecraft_username).await {
+        Ok(()) => info!(
+            "Ran the approval actions of `{}` for {}",
+            server.id, member.user.name
+        ),
+        Err(err) => warn!(
+            "Failed to run the approval actions of `{}` for {}: {err}",
+            server.id, member.user.name
+        ),
+    }
+}
+
+async fn reverse(
+    http: &Http,
+    on_approve: &OnApproveConfig,
+    server: &Server,
+    member: &Member,
+    minecraft_username: &str,
+) -> Result<(), Error> {
+    for role_id in &on_approve.roles {
+        member
+            .remove_role(http, RoleId::new(*role_id))
+            .await
+            .map_err(|err| format!("Failed to remove role `{role_id}`: {err}"))?;
+    }
+
+    // Nicknames the member changed since are left alone
+    if let Some(template) = &on_approve.nickname {
+        let nickname = render_nickname(template, server, member, minecraft_username);
+        if member.nick.as_deref() == Some(nickname.as_str()) {
+            member
+                .guild_id
+                .edit_member(http, member.user.id, EditMember::new().nickname(""))
+                .await
+                .map_err(|err| format!("Failed to reset the nickname: {err}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reverses the roles and nickname given by the server's `on_approve` actions once a player is
+/// removed from the whitelist, unless the member still has another account on the server.
+pub async fn undo_on_approve(
+    http: &Http,
+    db: &Pool<MySql>,
+    server: &Server,
+    guild_id: Option<u64>,
+    discord_id: &str,
+    minecraft_username: &str,
+) {
+    let Some(on_approve) = server.on_approve() else {
+        return;
+    };
+
+    let still_approved = sqlx::query!(
+        "
+        SELECT EXISTS(
+            SELECT 1 FROM whitelist_request
+            WHERE discord_id = ? AND server_id = ? AND status = ?
+        ) as 'exists'
+        ",
+        discord_id,
+        server.id,
+        WhitelistRequest::APPROVED
+    )
+    .fetch_one(db)
+    .await;
+    match still_approved {
+        Ok(row) if row.exists > 0 => return,
+        Ok(_) => {}
+        Err(err) => {
+            warn!(
+                "Failed to check the remaining access of `{discord_id}` on `{}`: {err}",
+                server.id
+            );
+            return;
+        }
+    }
+
+    let member = match fetch_member(http, guild_id, discord_id).await {
+        Ok(Some(member)) => member,
+        Ok(None) => return,
+        Err(err) => {
+            warn!(
+                "Failed to undo the approval actions of `{}` for `{discord_id}`: {err}",
+                server.id
+            );
+            return;
+        }
+    };
+
+    match reverse(http, on_approve, server, &member, minecraft_username).await {
+        Ok(()) => info!(
+            "Undid the approval actions of `{}` for {}",
+            server.id, member.user.name
+        ),
+        Err(err) => warn!(
+            "Failed to undo the approval actions of `{}` for {}: {err}",
+            server.id, member.user.name
+        ),
+    }
+}
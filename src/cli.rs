@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use bollard::Docker;
+use clap::{Parser, Subcommand};
+use poise::serenity_prelude::{Channel, ChannelId, Http, RoleId};
+use sqlx::{MySql, Pool};
+
+use crate::{
+    docker::ContainerResolver,
+    error::ApplicationError,
+    models::config::{Config, WhitelistConfig},
+    utils,
+    validation::{ConfigIssue, Severity},
+};
+
+/// How long to wait for each connectivity check before giving up.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A Discord bot for managing Docker-powered Minecraft servers.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Only register commands in the `commands.dev_guild` set in the config
+    #[arg(long, env = "BREEZE_DEV")]
+    pub dev: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Validate the config file and exit, exiting non-zero if it has errors
+    CheckConfig {
+        /// Also check that Docker, RCON, the database and Discord are reachable
+        #[arg(long)]
+        connectivity: bool,
+    },
+    /// Print the JSON Schema of the config file
+    ConfigSchema,
+}
+
+pub async fn run(command: Command) -> Result<(), ApplicationError> {
+    match command {
+        Command::CheckConfig { connectivity } => check_config(connectivity).await,
+        Command::ConfigSchema => {
+            print!("{}", Config::json_schema());
+            Ok(())
+        }
+    }
+}
+
+async fn check_config(connectivity: bool) -> Result<(), ApplicationError> {
+    println!("Checking {}", Config::path().display());
+
+    // Overrides stored in the database aren't checked, only the file itself
+    let (config, mut issues) = match Config::load_with_issues(&[]) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            println!("error: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    if connectivity {
+        issues.extend(check_connectivity(&config).await);
+    }
+
+    for issue in &issues {
+        let severity = match issue.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{severity}: {issue}");
+    }
+
+    let error_count = issues.iter().filter(|i| i.is_error()).count();
+    println!(
+        "{} error(s), {} warning(s)",
+        error_count,
+        issues.len() - error_count
+    );
+
+    if error_count > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+async fn check_connectivity(config: &Config) -> Vec<ConfigIssue> {
+    let mut issues = vec![];
+
+    match Docker::connect_with_defaults() {
+        Ok(docker) => match docker.ping().await {
+            Ok(_) => {
+                let resolver = ContainerResolver::default();
+                for (i, server) in config.servers.iter().enumerate() {
+                    if server.container_ref().is_none() {
+                        continue;
+                    }
+                    if let Err(err) = resolver.inspect(&docker, server).await {
+                        issues.push(ConfigIssue::error(format!("servers[{i}]"), err.to_string()));
+                    }
+                }
+            }
+            Err(err) => issues.push(ConfigIssue::error(
+                "docker",
+                format!("failed to reach the Docker daemon: {err}"),
+            )),
+        },
+        Err(err) => issues.push(ConfigIssue::error(
+            "docker",
+            format!("failed to connect to the Docker daemon: {err}"),
+        )),
+    }
+
+    for (i, server) in config.servers.iter().enumerate() {
+        if server.rcon_password.is_empty() {
+            continue;
+        }
+        let client = tokio::time::timeout(
+            CONNECT_TIMEOUT,
+            utils::create_rcon_client(
+                &server.address,
+                server.rcon_port,
+                server.rcon_password.expose().to_string(),
+            ),
+        )
+        .await;
+        let error = match client {
+            Ok(Ok(_)) => continue,
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+        issues.push(ConfigIssue::error(
+            format!("servers[{i}].rcon_port"),
+            format!(
+                "failed to connect to RCON at {}:{}: {error}",
+                server.address, server.rcon_port
+            ),
+        ));
+    }
+
+    match std::env::var("DATABASE_URL") {
+        Ok(database_url) => {
+            if let Err(err) = Pool::<MySql>::connect(&database_url).await {
+                issues.push(ConfigIssue::error(
+                    "database",
+                    format!("failed to connect: {err}"),
+                ));
+            }
+        }
+        Err(_) => issues.push(ConfigIssue::error("database", "`DATABASE_URL` is not set")),
+    }
+
+    match std::env::var("DISCORD_TOKEN") {
+        Ok(token) => issues.extend(check_discord(config, &Http::new(&token)).await),
+        Err(_) => issues.push(ConfigIssue::error("discord", "`DISCORD_TOKEN` is not set")),
+    }
+
+    issues
+}
+
+/// Checks that the request channels and all configured roles exist.
+async fn check_discord(config: &Config, http: &Http) -> Vec<ConfigIssue> {
+    let mut issues = vec![];
+
+    if let Some(whitelist) = &config.whitelist {
+        issues.extend(check_whitelist_discord("whitelist", whitelist, None, http).await);
+    }
+    for (i, guild) in config.guilds.iter().enumerate() {
+        if let Some(whitelist) = &guild.whitelist {
+            let path = format!("guilds[{i}].whitelist");
+            issues.extend(check_whitelist_discord(&path, whitelist, Some(guild.id), http).await);
+        }
+    }
+    for (i, server) in config.servers.iter().enumerate() {
+        let Some(whitelist) = &server.whitelist else {
+            continue;
+        };
+        // Without a channel override there's no guild to look the roles up in
+        if let Some(request_channel) = whitelist.request_channel {
+            let role_lists = [
+                (
+                    "ping_roles",
+                    whitelist.ping_roles.as_deref().unwrap_or_default(),
+                ),
+                (
+                    "approver_roles",
+                    whitelist.approver_roles.as_deref().unwrap_or_default(),
+                ),
+            ];
+            let path = format!("servers[{i}].whitelist");
+            issues.extend(
+                check_channel_discord(&path, request_channel, &role_lists, None, http).await,
+            );
+        }
+    }
+
+    issues
+}
+
+async fn check_whitelist_discord(
+    path: &str,
+    whitelist: &WhitelistConfig,
+    expected_guild: Option<u64>,
+    http: &Http,
+) -> Vec<ConfigIssue> {
+    let role_lists = [
+        ("allowed_roles", whitelist.allowed_roles.as_slice()),
+        ("ping_roles", whitelist.ping_roles.as_slice()),
+    ];
+    check_channel_discord(
+        path,
+        whitelist.request_channel,
+        &role_lists,
+        expected_guild,
+        http,
+    )
+    .await
+}
+
+/// Checks that the request channel exists and that the roles exist in the channel's guild.
+async fn check_channel_discord(
+    path: &str,
+    request_channel: u64,
+    role_lists: &[(&str, &[u64])],
+    expected_guild: Option<u64>,
+    http: &Http,
+) -> Vec<ConfigIssue> {
+    let mut issues = vec![];
+
+    // Zero IDs are already reported by validation and can't be looked up
+    if request_channel == 0 {
+        return issues;
+    }
+
+    let guild_id = match http.get_channel(ChannelId::new(request_channel)).await {
+        Ok(Channel::Guild(channel)) => channel.guild_id,
+        Ok(_) => {
+            issues.push(ConfigIssue::error(
+                format!("{path}.request_channel"),
+                "is not a server channel",
+            ));
+            return issues;
+        }
+        Err(err) => {
+            issues.push(ConfigIssue::error(
+                format!("{path}.request_channel"),
+                format!("unknown channel `{}`: {err}", request_channel),
+            ));
+            return issues;
+        }
+    };
+
+    if expected_guild.is_some_and(|expected| expected != guild_id.get()) {
+        issues.push(ConfigIssue::error(
+            format!("{path}.request_channel"),
+            format!("channel belongs to a different guild (`{guild_id}`)"),
+        ));
+    }
+
+    let roles = match guild_id.roles(http).await {
+        Ok(roles) => roles,
+        Err(err) => {
+            issues.push(ConfigIssue::error(
+                path,
+                format!("failed to fetch roles of guild `{guild_id}`: {err}"),
+            ));
+            return issues;
+        }
+    };
+
+    for (field, role_ids) in role_lists {
+        for (i, role_id) in role_ids.iter().enumerate() {
+            if *role_id != 0 && !roles.contains_key(&RoleId::new(*role_id)) {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.{field}[{i}]"),
+                    format!("unknown role `{role_id}`"),
+                ));
+            }
+        }
+    }
+
+    issues
+}
@@ -0,0 +1,119 @@
+use crate::{Context, Error, permissions, registration, reload};
+use poise::{
+    CreateReply,
+    serenity_prelude::{self as serenity, CreateEmbed},
+};
+
+/// Commands for managing breeze itself
+#[poise::command(
+    slash_command,
+    subcommands("reload", "commands"),
+    check = "permissions::can_manage_bot"
+)]
+pub async fn breeze(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reload the config file without restarting the bot
+#[poise::command(slash_command)]
+async fn reload(ctx: Context<'_>) -> Result<(), Error> {
+    let embed = match reload::reload(&ctx.data().config, &ctx.data().db).await {
+        Ok(diff) => CreateEmbed::new()
+            .title("🔄 Config reloaded")
+            .description(diff.to_string())
+            .color(0x40a02b),
+        Err(err) => CreateEmbed::new()
+            .title("⁉️ Failed to reload config")
+            .description(format!(
+                "The config was not reloaded and the current config is still in use.\n```{err}```"
+            ))
+            .color(0xd20f39),
+    };
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Manage where slash commands are registered
+#[poise::command(slash_command, subcommands("register", "clear"))]
+async fn commands(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+enum CommandScope {
+    #[name = "Global"]
+    Global,
+    #[name = "This guild"]
+    Guild,
+}
+
+/// Re-register slash commands where the config says to
+#[poise::command(slash_command)]
+async fn register(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let config = ctx.data().config.load_full();
+    let registered = registration::register_commands(
+        ctx.http(),
+        &ctx.framework().options().commands,
+        &config.commands,
+        ctx.data().dev_mode,
+    )
+    .await?;
+
+    ctx.send(
+        CreateReply::default()
+            .embed(
+                CreateEmbed::new()
+                    .title("✅ Commands registered")
+                    .description(format!("Registered commands {registered}."))
+                    .color(0x40a02b),
+            )
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Remove stale slash commands, e.g. global ones left over from before using guild commands
+#[poise::command(slash_command)]
+async fn clear(
+    ctx: Context<'_>,
+    #[description = "Which commands to remove"] scope: CommandScope,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let cleared = match scope {
+        CommandScope::Global => {
+            serenity::Command::set_global_commands(ctx.http(), vec![]).await?;
+            "global commands".to_string()
+        }
+        CommandScope::Guild => {
+            let guild_id = ctx
+                .guild_id()
+                .ok_or("This command can only clear guild commands inside a guild")?;
+            guild_id.set_commands(ctx.http(), vec![]).await?;
+            format!("commands registered in guild `{guild_id}`")
+        }
+    };
+
+    ctx.send(
+        CreateReply::default()
+            .embed(
+                CreateEmbed::new()
+                    .title("🧹 Commands cleared")
+                    .description(format!(
+                        "Removed all {cleared}. Use `/breeze commands register` to register them again where the config says to."
+                    ))
+                    .color(0x40a02b),
+            )
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,508 @@
+use crate::{
+    Context, Error,
+    error::ApplicationError,
+    models::{
+        config::{Config, Server, WhitelistConfig},
+        database::ConfigOverride,
+    },
+    overrides::{self, GLOBAL_SCOPE, OverrideChange, REMOVED_SETTING},
+    permissions::{self, Permission},
+    reload::ConfigDiff,
+    utils::{self, autocomplete_server_ids},
+};
+use poise::{CreateReply, serenity_prelude::CreateEmbed};
+use schemars::JsonSchema;
+
+/// View and edit the config without touching the config file
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("view", "set", "unset", "server", "history"),
+    check = "permissions::can_manage_config"
+)]
+pub async fn config(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Returns the names of the top-level fields of a config struct.
+fn setting_names<T: JsonSchema>() -> Vec<String> {
+    schemars::schema_for!(T)
+        .schema
+        .object
+        .map(|object| object.properties.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+async fn autocomplete_whitelist_settings(
+    _: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = String> {
+    let partial = partial.to_string();
+    setting_names::<WhitelistConfig>()
+        .into_iter()
+        .filter(move |s| s.starts_with(&partial))
+}
+
+async fn autocomplete_server_settings(
+    _: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = String> {
+    let partial = partial.to_string();
+    setting_names::<Server>()
+        .into_iter()
+        .filter(move |s| s != "id" && s.starts_with(&partial))
+}
+
+/// Hides values of settings that hold secrets.
+fn display_value(setting: &str, value: &str) -> String {
+    overrides::redact(setting, value)
+}
+
+/// Longest description Discord accepts in an embed.
+const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+
+/// Describes the settings and the overrides among them, cutting both short to fit in an embed.
+fn format_settings(settings: &str, overrides: &[&ConfigOverride]) -> String {
+    let settings = format!(
+        "```toml\n{}```\n**Overrides:**\n",
+        utils::truncate(settings, EMBED_DESCRIPTION_LIMIT / 2)
+    );
+    let overrides = utils::truncate(
+        &format_overrides(overrides),
+        EMBED_DESCRIPTION_LIMIT - settings.chars().count(),
+    );
+
+    format!("{settings}{overrides}")
+}
+
+fn format_overrides(overrides: &[&ConfigOverride]) -> String {
+    if overrides.is_empty() {
+        return "_None_".to_string();
+    }
+
+    overrides
+        .iter()
+        .map(|o| {
+            format!(
+                "- `{}` `{}` = `{}` (by <@{}>)",
+                o.scope,
+                o.setting,
+                display_value(&o.setting, &o.value),
+                o.updated_by
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Returns the scope whitelist settings of the given guild are stored in. Guilds without their
+/// own whitelist settings use the top-level ones.
+fn whitelist_scope(config: &Config, guild_id: u64) -> String {
+    match config
+        .guild(guild_id)
+        .is_some_and(|g| g.whitelist.is_some())
+    {
+        true => overrides::guild_scope(guild_id),
+        false => GLOBAL_SCOPE.to_string(),
+    }
+}
+
+/// Applies the changes and replies with what changed, or with why the change was rejected.
+async fn apply_changes(
+    ctx: Context<'_>,
+    changes: Vec<OverrideChange>,
+    description: String,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    let result = overrides::change(&data.config, &data.db, changes, ctx.author().id.get()).await;
+
+    let embed = match result {
+        Ok(diff) => CreateEmbed::new()
+            .title("✅ Config updated")
+            .description(format!("{description}\n\n{}", format_diff(&diff)))
+            .color(0x40a02b),
+        Err(ApplicationError::InvalidConfig(issues)) => CreateEmbed::new()
+            .title("⁉️ Change rejected")
+            .description(format!(
+                "The change would make the config invalid, so it wasn't applied.\n```{issues}```"
+            ))
+            .color(0xd20f39),
+        Err(err) => return Err(err.into()),
+    };
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+fn format_diff(diff: &ConfigDiff) -> String {
+    match diff.is_empty() {
+        true => "No effective changes.".to_string(),
+        false => diff.to_string(),
+    }
+}
+
+/// View the whitelist settings of this server and where they were changed at runtime
+#[poise::command(slash_command, check = "permissions::can_manage_global_config")]
+async fn view(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?.get();
+
+    let whitelist = config
+        .whitelist_for(Some(guild_id))
+        .ok_or("Whitelist requests aren't set up for this server")?;
+
+    let overrides = overrides::fetch(&ctx.data().db).await?;
+    let scopes = [GLOBAL_SCOPE.to_string(), overrides::guild_scope(guild_id)];
+    let relevant: Vec<&ConfigOverride> = overrides
+        .iter()
+        .filter(|o| scopes.contains(&o.scope))
+        .collect();
+
+    let embed = CreateEmbed::new()
+        .title("⚙️ Whitelist settings")
+        .color(0x04a5e5)
+        .description(format_settings(
+            &toml::to_string_pretty(whitelist)?,
+            &relevant,
+        ));
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Change a whitelist setting of this server
+#[poise::command(slash_command, check = "permissions::can_manage_global_config")]
+async fn set(
+    ctx: Context<'_>,
+    #[description = "Name of the setting"]
+    #[autocomplete = "autocomplete_whitelist_settings"]
+    setting: String,
+    #[description = "New value, in TOML syntax (e.g. `true` or `[123, 456]`)"] value: String,
+) -> Result<(), Error> {
+    if !setting_names::<WhitelistConfig>().contains(&setting) {
+        return Err(format!("Unknown whitelist setting `{setting}`").into());
+    }
+
+    let config = ctx.data().config.load_full();
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?.get();
+    let value = overrides::normalize_value(&value);
+
+    let change = OverrideChange {
+        scope: whitelist_scope(&config, guild_id),
+        setting: format!("whitelist.{setting}"),
+        value: Some(value.clone()),
+    };
+    let description = format!(
+        "Set `{}` `{}` to `{}`.",
+        change.scope,
+        change.setting,
+        display_value(&change.setting, &value)
+    );
+
+    apply_changes(ctx, vec![change], description).await
+}
+
+/// Reset a whitelist setting of this server to the value from the config file
+#[poise::command(slash_command, check = "permissions::can_manage_global_config")]
+async fn unset(
+    ctx: Context<'_>,
+    #[description = "Name of the setting"]
+    #[autocomplete = "autocomplete_whitelist_settings"]
+    setting: String,
+) -> Result<(), Error> {
+    if !setting_names::<WhitelistConfig>().contains(&setting) {
+        return Err(format!("Unknown whitelist setting `{setting}`").into());
+    }
+
+    let config = ctx.data().config.load_full();
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?.get();
+
+    let change = OverrideChange {
+        scope: whitelist_scope(&config, guild_id),
+        setting: format!("whitelist.{setting}"),
+        value: None,
+    };
+    let description = format!("Reset `{}` `{}`.", change.scope, change.setting);
+
+    apply_changes(ctx, vec![change], description).await
+}
+
+/// Manage servers
+#[poise::command(
+    slash_command,
+    subcommands(
+        "server_view",
+        "server_add",
+        "server_remove",
+        "server_set",
+        "server_unset"
+    )
+)]
+async fn server(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// View a server's settings and where they were changed at runtime
+#[poise::command(slash_command, rename = "view")]
+async fn server_view(
+    ctx: Context<'_>,
+    #[description = "ID of the server"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: String,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let server = config
+        .servers_for(ctx.guild_id().map(|id| id.get()))
+        .into_iter()
+        .find(|s| s.id == server_id)
+        .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::ConfigManage, Some(server)).await?;
+
+    let mut server_toml = toml::Table::try_from(server)?;
+    if !server.rcon_password.is_empty() {
+        server_toml.insert("rcon_password".to_string(), "***".into());
+    }
+
+    let overrides = overrides::fetch(&ctx.data().db).await?;
+    let scope = overrides::server_scope(&server_id);
+    let relevant: Vec<&ConfigOverride> = overrides.iter().filter(|o| o.scope == scope).collect();
+
+    let embed = CreateEmbed::new()
+        .title(format!("⚙️ {}", server.name))
+        .color(0x04a5e5)
+        .description(format_settings(
+            &toml::to_string_pretty(&server_toml)?,
+            &relevant,
+        ));
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Add a new server
+#[allow(clippy::too_many_arguments)]
+#[poise::command(
+    slash_command,
+    rename = "add",
+    check = "permissions::can_manage_global_config"
+)]
+async fn server_add(
+    ctx: Context<'_>,
+    #[description = "ID of the new server"] id: String,
+    #[description = "Display name of the server"] name: String,
+    #[description = "Query port of the server"] query_port: u16,
+    #[description = "RCON port of the server"] rcon_port: u16,
+    #[description = "Address of the server, defaults to localhost"] address: Option<String>,
+    #[description = "RCON password of the server"] rcon_password: Option<String>,
+    #[description = "ID of the server's container"] container_id: Option<String>,
+    #[description = "Name of the server's container"] container_name: Option<String>,
+    #[description = "Compose project of the server's container"] compose_project: Option<String>,
+    #[description = "Compose service of the server's container"] compose_service: Option<String>,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    if config.servers.iter().any(|s| s.id == id) {
+        return Err(format!("A server with the ID `{id}` already exists").into());
+    }
+
+    let scope = overrides::server_scope(&id);
+    let string_settings = [
+        ("name", Some(name)),
+        ("address", address),
+        ("rcon_password", rcon_password),
+        ("container_id", container_id),
+        ("container_name", container_name),
+        ("compose_project", compose_project),
+        ("compose_service", compose_service),
+    ];
+
+    let mut changes: Vec<OverrideChange> = string_settings
+        .into_iter()
+        .filter_map(|(setting, value)| {
+            value.map(|value| OverrideChange {
+                scope: scope.clone(),
+                setting: setting.to_string(),
+                value: Some(toml::Value::String(value).to_string()),
+            })
+        })
+        .collect();
+    for (setting, port) in [("query_port", query_port), ("rcon_port", rcon_port)] {
+        changes.push(OverrideChange {
+            scope: scope.clone(),
+            setting: setting.to_string(),
+            value: Some(port.to_string()),
+        });
+    }
+    // The server may have been removed before
+    changes.push(OverrideChange {
+        scope: scope.clone(),
+        setting: REMOVED_SETTING.to_string(),
+        value: None,
+    });
+
+    apply_changes(ctx, changes, format!("Added server `{id}`.")).await
+}
+
+/// Remove a server
+#[poise::command(slash_command, rename = "remove")]
+async fn server_remove(
+    ctx: Context<'_>,
+    #[description = "ID of the server"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: String,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let server = config
+        .servers_for(ctx.guild_id().map(|id| id.get()))
+        .into_iter()
+        .find(|s| s.id == server_id)
+        .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::ConfigManage, Some(server)).await?;
+
+    let scope = overrides::server_scope(&server_id);
+    let mut changes: Vec<OverrideChange> = overrides::fetch(&ctx.data().db)
+        .await?
+        .into_iter()
+        .filter(|o| o.scope == scope)
+        .map(|o| OverrideChange {
+            scope: o.scope,
+            setting: o.setting,
+            value: None,
+        })
+        .collect();
+    // Servers defined in the config file stay hidden
+    changes.push(OverrideChange {
+        scope,
+        setting: REMOVED_SETTING.to_string(),
+        value: Some("true".to_string()),
+    });
+
+    apply_changes(ctx, changes, format!("Removed server `{server_id}`.")).await
+}
+
+/// Change a server's setting
+#[poise::command(slash_command, rename = "set")]
+async fn server_set(
+    ctx: Context<'_>,
+    #[description = "ID of the server"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: String,
+    #[description = "Name of the setting"]
+    #[autocomplete = "autocomplete_server_settings"]
+    setting: String,
+    #[description = "New value, in TOML syntax (e.g. `25565` or `\"localhost\"`)"] value: String,
+) -> Result<(), Error> {
+    if setting == "id" || !setting_names::<Server>().contains(&setting) {
+        return Err(format!("Unknown server setting `{setting}`").into());
+    }
+
+    let config = ctx.data().config.load_full();
+    let server = config
+        .servers_for(ctx.guild_id().map(|id| id.get()))
+        .into_iter()
+        .find(|s| s.id == server_id)
+        .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::ConfigManage, Some(server)).await?;
+
+    let value = overrides::normalize_value(&value);
+    let description = format!(
+        "Set `{setting}` of server `{server_id}` to `{}`.",
+        display_value(&setting, &value)
+    );
+    let change = OverrideChange {
+        scope: overrides::server_scope(&server_id),
+        setting,
+        value: Some(value),
+    };
+
+    apply_changes(ctx, vec![change], description).await
+}
+
+/// Reset a server's setting to the value from the config file
+#[poise::command(slash_command, rename = "unset")]
+async fn server_unset(
+    ctx: Context<'_>,
+    #[description = "ID of the server"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: String,
+    #[description = "Name of the setting"]
+    #[autocomplete = "autocomplete_server_settings"]
+    setting: String,
+) -> Result<(), Error> {
+    if setting == "id" || !setting_names::<Server>().contains(&setting) {
+        return Err(format!("Unknown server setting `{setting}`").into());
+    }
+
+    let config = ctx.data().config.load_full();
+    let server = config
+        .servers_for(ctx.guild_id().map(|id| id.get()))
+        .into_iter()
+        .find(|s| s.id == server_id)
+        .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::ConfigManage, Some(server)).await?;
+
+    let description = format!("Reset `{setting}` of server `{server_id}`.");
+    let change = OverrideChange {
+        scope: overrides::server_scope(&server_id),
+        setting,
+        value: None,
+    };
+
+    apply_changes(ctx, vec![change], description).await
+}
+
+/// Show recent changes made to the config
+#[poise::command(slash_command, check = "permissions::can_manage_global_config")]
+async fn history(
+    ctx: Context<'_>,
+    #[description = "Number of changes to show"]
+    #[min = 1]
+    #[max = 25]
+    limit: Option<u32>,
+) -> Result<(), Error> {
+    let entries = overrides::history(&ctx.data().db, limit.unwrap_or(10)).await?;
+
+    let format_value = |setting: &str, value: &Option<String>| match value {
+        Some(value) => format!("`{}`", display_value(setting, value)),
+        None => "_unset_".to_string(),
+    };
+
+    let description = match entries.is_empty() {
+        true => "No changes have been made yet.".to_string(),
+        false => entries
+            .iter()
+            .map(|entry| {
+                let changed_at = entry
+                    .changed_at
+                    .map(|t| format!("<t:{}:R>", t.timestamp()))
+                    .unwrap_or_default();
+                format!(
+                    "**#{}** {} by <@{}>\n`{}` `{}`: {} → {}",
+                    entry.id,
+                    changed_at,
+                    entry.changed_by,
+                    entry.scope,
+                    entry.setting,
+                    format_value(&entry.setting, &entry.old_value),
+                    format_value(&entry.setting, &entry.new_value)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+    };
+
+    let embed = CreateEmbed::new()
+        .title("📜 Config history")
+        .color(0x04a5e5)
+        .description(utils::truncate(&description, EMBED_DESCRIPTION_LIMIT));
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
@@ -1 +1,4 @@
+pub mod breeze;
+pub mod config;
+pub mod permissions;
 pub mod servers;
@@ -0,0 +1,158 @@
+use crate::{
+    Context, Error,
+    permissions::{self, Permission},
+    utils::autocomplete_server_ids,
+};
+use poise::{
+    CreateReply,
+    serenity_prelude::{self as serenity, CreateEmbed, Permissions},
+};
+
+/// Inspect who can do what
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("list", "check"),
+    check = "permissions::can_manage_config"
+)]
+pub async fn permissions(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// List the roles granted each permission
+#[poise::command(slash_command)]
+async fn list(
+    ctx: Context<'_>,
+    #[description = "Only show permissions on this server"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: Option<String>,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?.get();
+
+    let server = match &server_id {
+        Some(server_id) => Some(
+            config
+                .servers_for(Some(guild_id))
+                .into_iter()
+                .find(|server| &server.id == server_id)
+                .ok_or(format!("Server with ID `{}` not found", server_id))?,
+        ),
+        None => None,
+    };
+    let whitelist = config.whitelist_for(Some(guild_id));
+
+    let fields = Permission::ALL.iter().map(|permission| {
+        let mut roles = permissions::granted_roles(&config, *permission, server);
+        if let (Permission::WhitelistReview, Some(whitelist)) = (permission, whitelist) {
+            let approvers = permissions::approver_roles(&config, whitelist, server);
+            let roles = roles.get_or_insert_default();
+            roles.extend(approvers);
+            roles.sort_unstable();
+            roles.dedup();
+        }
+
+        let mut lines: Vec<String> = match roles {
+            None if permission.public_by_default() => vec!["Everyone".to_string()],
+            roles => roles
+                .unwrap_or_default()
+                .iter()
+                .map(|role_id| match *role_id == guild_id {
+                    true => "Everyone".to_string(),
+                    false => format!("<@&{role_id}>"),
+                })
+                .collect(),
+        };
+
+        let admins_allowed =
+            *permission != Permission::WhitelistReview || whitelist.is_none_or(|w| w.allow_admin);
+        if admins_allowed {
+            lines.push("Administrators".to_string());
+        }
+        if lines.is_empty() {
+            lines.push("_Nobody_".to_string());
+        }
+
+        (format!("`{permission}`"), lines.join("\n"), true)
+    });
+
+    let title = match server {
+        Some(server) => format!("🔐 Permissions on {}", server.name),
+        None => "🔐 Permissions".to_string(),
+    };
+    let embed = CreateEmbed::new()
+        .title(title)
+        .color(0x04a5e5)
+        .fields(fields.collect::<Vec<(String, String, bool)>>());
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Check which permissions a member has
+#[poise::command(slash_command)]
+async fn check(
+    ctx: Context<'_>,
+    #[description = "Member to check"] member: serenity::Member,
+    #[description = "Check permissions on this server"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: Option<String>,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?.get();
+
+    let server = match &server_id {
+        Some(server_id) => Some(
+            config
+                .servers_for(Some(guild_id))
+                .into_iter()
+                .find(|server| &server.id == server_id)
+                .ok_or(format!("Server with ID `{}` not found", server_id))?,
+        ),
+        None => None,
+    };
+
+    // Members passed as arguments don't come with their guild permissions
+    let mut member = member;
+    if let Some(guild) = ctx.guild() {
+        member.permissions = Some(match guild.owner_id == member.user.id {
+            true => Permissions::all(),
+            false => guild
+                .roles
+                .values()
+                .filter(|role| role.id.get() == guild_id || member.roles.contains(&role.id))
+                .fold(Permissions::empty(), |acc, role| acc | role.permissions),
+        });
+    }
+
+    let lines = Permission::ALL
+        .iter()
+        .map(|permission| {
+            let icon = match permissions::has_permission(&config, &member, *permission, server) {
+                true => "✅",
+                false => "❌",
+            };
+            format!("{icon} `{permission}`")
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let scope = match server {
+        Some(server) => format!("on _**{}**_", server.name),
+        None => "on any server".to_string(),
+    };
+    let embed = CreateEmbed::new()
+        .title("🔐 Member permissions")
+        .color(0x04a5e5)
+        .description(format!(
+            "Permissions of <@{}> {scope}:\n\n{lines}",
+            member.user.id
+        ));
+
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
@@ -1,4 +1,12 @@
-use crate::{Context, Error, models::config::Server, utils::autocomplete_server_ids};
+use std::collections::HashMap;
+
+use crate::{
+    Context, Error,
+    error::ApplicationError,
+    models::config::{Config, Server},
+    permissions::{self, Permission},
+    utils::autocomplete_server_ids,
+};
 use bollard::{Docker, secret::ContainerStateStatusEnum};
 use poise::{CreateReply, serenity_prelude as serenity};
 use serenity::{CreateEmbed, CreateEmbedFooter};
@@ -27,42 +35,134 @@ pub struct ServerListEntry {
     pub additional_info: Option<ServerAdditionalInfo>,
 }
 
-fn create_server_list_fields(servers: Vec<ServerListEntry>) -> Vec<(String, String, bool)> {
-    let mut fields: Vec<(String, String, bool)> = vec![];
-    for server in servers {
-        let additional_info = server.additional_info;
+fn format_players(info: &Option<ServerAdditionalInfo>) -> String {
+    match info {
+        Some(info) => format!("`{}/{}`", info.players_online, info.players_max),
+        None => "`?`".to_string(),
+    }
+}
 
-        let server_status: &str = server.status.into();
+fn create_server_field(server: ServerListEntry) -> (String, String, bool) {
+    let server_status: &str = server.status.into();
 
-        let field = match additional_info {
-            Some(info) => (
-                server.server.name,
-                format!(
-                    "**ID:** `{}`\n**Status:** {}\n**Players:** `{}/{}`\n**Version:** `{}`",
-                    server.server.id,
-                    server_status,
-                    info.players_online,
-                    info.players_max,
-                    info.version
-                ),
-                false,
+    match server.additional_info {
+        Some(info) => (
+            server.server.name,
+            format!(
+                "**ID:** `{}`\n**Status:** {}\n**Players:** `{}/{}`\n**Version:** `{}`",
+                server.server.id,
+                server_status,
+                info.players_online,
+                info.players_max,
+                info.version
             ),
-            None => (
-                server.server.name,
-                format!(
-                    "**ID:** `{}`\n**Status:** {}",
-                    server.server.id, server_status
-                ),
-                false,
+            false,
+        ),
+        None => (
+            server.server.name,
+            format!(
+                "**ID:** `{}`\n**Status:** {}",
+                server.server.id, server_status
             ),
-        };
-        fields.push(field);
+            false,
+        ),
     }
+}
 
-    fields
+/// Shows the servers of a group in one field. The players of a group with a proxy are counted
+/// by the proxy, since it sees the whole network, and otherwise summed up over its servers.
+fn create_group_field(
+    config: &Config,
+    group_id: &str,
+    servers: Vec<ServerListEntry>,
+) -> (String, String, bool) {
+    let group = config.group(group_id);
+    let name = group.map_or(group_id.to_string(), |g| g.name.clone());
+    let proxy = group.and_then(|g| g.proxy.as_deref());
+
+    let proxy_info = servers
+        .iter()
+        .find(|s| Some(s.server.id.as_str()) == proxy)
+        .and_then(|s| s.additional_info.as_ref());
+    let (online, max) = match proxy_info {
+        Some(info) => (info.players_online, info.players_max),
+        None => servers
+            .iter()
+            .filter_map(|s| s.additional_info.as_ref())
+            .fold((0, 0), |(online, max), info| {
+                (online + info.players_online, max + info.players_max)
+            }),
+    };
+
+    let lines: Vec<String> = servers
+        .into_iter()
+        .map(|server| {
+            let server_status: &str = server.status.into();
+            let role = match Some(server.server.id.as_str()) == proxy {
+                true => " (proxy)",
+                false => "",
+            };
+            format!(
+                "- **{}** (`{}`){role}: {}, {} players",
+                server.server.name,
+                server.server.id,
+                server_status,
+                format_players(&server.additional_info)
+            )
+        })
+        .collect();
+
+    (
+        name,
+        format!(
+            "**Group ID:** `{group_id}`\n**Players:** `{online}/{max}`\n{}",
+            lines.join("\n")
+        ),
+        false,
+    )
 }
 
-#[poise::command(slash_command, subcommands("list", "players"))]
+fn create_server_list_fields(
+    config: &Config,
+    servers: Vec<ServerListEntry>,
+) -> Vec<(String, String, bool)> {
+    // Groups are listed where their first server is
+    let mut order: Vec<Option<String>> = vec![];
+    let mut grouped: HashMap<String, Vec<ServerListEntry>> = HashMap::new();
+    let mut ungrouped: Vec<ServerListEntry> = vec![];
+    for server in servers {
+        match server.server.group.clone() {
+            Some(group) => {
+                if !grouped.contains_key(&group) {
+                    order.push(Some(group.clone()));
+                }
+                grouped.entry(group).or_default().push(server);
+            }
+            None => {
+                order.push(None);
+                ungrouped.push(server);
+            }
+        }
+    }
+
+    let mut ungrouped = ungrouped.into_iter();
+    order
+        .into_iter()
+        .filter_map(|group| match group {
+            Some(group) => {
+                let servers = grouped.remove(&group)?;
+                Some(create_group_field(config, &group, servers))
+            }
+            None => ungrouped.next().map(create_server_field),
+        })
+        .collect()
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("list", "players"),
+    check = "permissions::can_view_servers"
+)]
 pub async fn server(_: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
@@ -74,8 +174,20 @@ async fn list(ctx: Context<'_>) -> Result<(), Error> {
 
     match Docker::connect_with_defaults() {
         Ok(docker) => {
-            for server in &ctx.data().config.servers {
-                let container = docker.inspect_container(&server.container_id, None).await?;
+            let config = ctx.data().config.load_full();
+            for server in config.servers_for(ctx.guild_id().map(|id| id.get())) {
+                if permissions::require(ctx, Permission::ServerView, Some(server))
+                    .await
+                    .is_err()
+                {
+                    continue;
+                }
+
+                let container = match ctx.data().containers.inspect(&docker, server).await {
+                    Ok(container) => Some(container),
+                    Err(ApplicationError::ContainerNotFound(_)) => None,
+                    Err(err) => return Err(err.into()),
+                };
 
                 let mut additional_info: Option<ServerAdditionalInfo> = None;
 
@@ -87,10 +199,13 @@ async fn list(ctx: Context<'_>) -> Result<(), Error> {
                     });
                 }
 
-                let server_status = match container.state.unwrap().status.unwrap() {
-                    ContainerStateStatusEnum::RUNNING => ServerStatus::Online,
-                    ContainerStateStatusEnum::CREATED => ServerStatus::Offline,
-                    ContainerStateStatusEnum::RESTARTING => ServerStatus::Restarting,
+                let server_status = match container
+                    .and_then(|c| c.state)
+                    .and_then(|state| state.status)
+                {
+                    Some(ContainerStateStatusEnum::RUNNING) => ServerStatus::Online,
+                    Some(ContainerStateStatusEnum::CREATED) => ServerStatus::Offline,
+                    Some(ContainerStateStatusEnum::RESTARTING) => ServerStatus::Restarting,
                     _ => ServerStatus::Offline, // Default to offline, might change this later
                 };
 
@@ -105,7 +220,7 @@ async fn list(ctx: Context<'_>) -> Result<(), Error> {
                 .title("ℹ️ Servers")
                 .color(0x04a5e5)
                 .description("List of servers with info n' stuff!")
-                .fields(create_server_list_fields(server_list))
+                .fields(create_server_list_fields(&config, server_list))
                 .footer(CreateEmbedFooter::new(
                     "Looking for a list of players? Use `/server players`!",
                 ));
@@ -129,13 +244,14 @@ async fn players(
     #[autocomplete = "autocomplete_server_ids"]
     server_id: String,
 ) -> Result<(), Error> {
-    let config = &ctx.data().config;
+    let config = ctx.data().config.load_full();
 
     let server = config
-        .servers
-        .iter()
+        .servers_for(ctx.guild_id().map(|id| id.get()))
+        .into_iter()
         .find(|server| server.id == server_id)
         .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::ServerView, Some(server)).await?;
 
     let query = match mc_query::status(&server.address, server.query_port).await {
         Ok(query) => query,
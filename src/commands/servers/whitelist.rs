@@ -1,36 +1,330 @@
-use crate::{Context, Error, utils::autocomplete_server_ids};
+use std::time::Duration;
+
+use crate::{
+    Context, Error, actions,
+    models::{
+        config::{FormQuestion, Server, WhitelistConfig},
+        database::{WhitelistAnswer, WhitelistRequest, WhitelistVote},
+    },
+    permissions::{self, Permission},
+    requests::{self, Conflict, Edition},
+    sync as role_sync,
+    utils::{self, autocomplete_request_targets, autocomplete_server_ids},
+};
 use chrono::Utc;
 use poise::{CreateReply, serenity_prelude as serenity};
 use serenity::{
-    ButtonStyle, ChannelId, CreateButton, CreateEmbed, CreateEmbedFooter, CreateMessage,
+    AutocompleteChoice, ButtonStyle, ChannelId, ComponentInteraction,
+    ComponentInteractionCollector, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter,
+    CreateInputText, CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage, CreateMessage, CreateQuickModal, EditMessage, InputTextStyle,
+    ModalInteraction,
 };
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-#[poise::command(slash_command, subcommands("request"))]
+/// How long a requester has to fill out each page of an application form.
+const FORM_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Embed characters shared by all answers, leaving room for the rest of the request embed
+/// within Discord's limit of 6000 characters per embed.
+const ANSWERS_LENGTH: usize = 3000;
+
+/// Discord's limit for the value of an embed field.
+const FIELD_VALUE_LENGTH: usize = 1024;
+
+#[poise::command(
+    slash_command,
+    subcommands("request", "status", "cancel", "edit", "grant", "sync"),
+    check = "permissions::can_request_whitelist"
+)]
 pub async fn whitelist(_: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Mentions everyone who cast the given vote.
+pub fn format_voters(votes: &[WhitelistVote], vote: &str) -> String {
+    let voters = votes
+        .iter()
+        .filter(|v| v.vote == vote)
+        .map(|v| format!("<@{}>", v.reviewer_id))
+        .collect::<Vec<String>>();
+
+    match voters.is_empty() {
+        true => "_None_".to_string(),
+        false => voters.join(", "),
+    }
+}
+
+/// Creates the approve and deny buttons of a request.
+pub fn create_request_buttons(request_id: &str, disabled: bool) -> Vec<CreateButton> {
+    vec![
+        CreateButton::new(format!("wlreq-approve-{request_id}"))
+            .label("Approve")
+            .style(ButtonStyle::Success)
+            .disabled(disabled),
+        CreateButton::new(format!("wlreq-approvefor-{request_id}"))
+            .label("Approve for…")
+            .style(ButtonStyle::Secondary)
+            .disabled(disabled),
+        CreateButton::new(format!("wlreq-deny-{request_id}"))
+            .label("Deny")
+            .style(ButtonStyle::Danger)
+            .disabled(disabled),
+    ]
+}
+
+/// Creates the message posted to the request channel for a pending request, pinging the server's
+/// reviewers.
+pub fn create_request_message(
+    request: &WhitelistRequest,
+    server: &Server,
+    whitelist_config: &WhitelistConfig,
+    embed: CreateEmbed,
+) -> CreateMessage {
+    let pings = server
+        .ping_roles(whitelist_config)
+        .iter()
+        .map(|role_id| format!("<@&{}>", role_id))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    CreateMessage::new()
+        .add_embed(embed)
+        .content(pings)
+        .components(vec![CreateActionRow::Buttons(create_request_buttons(
+            &request.id,
+            false,
+        ))])
+}
+
+/// Creates the embed posted to the request channel, including the tally of the votes cast so far
+/// if the server needs more than one decision, the answers to the server's application form, and
+/// any conflicting requests.
+pub fn create_request_embed(
+    request: &WhitelistRequest,
+    server: &Server,
+    votes: &[WhitelistVote],
+    answers: &[WhitelistAnswer],
+    conflicts: &[Conflict],
+) -> CreateEmbed {
+    let container = server
+        .container_ref()
+        .map(|c| c.to_string())
+        .unwrap_or_else(|| "none".to_string());
+
+    let mut description = format!(
+        "<@{}> has requested to be whitelisted on server _{}_!\n\n**Minecraft Username:** `{}`\n**Server ID:** `{}`\n**Container:** `{}`\n**Request ID:** `{}`",
+        request.discord_id,
+        server.name,
+        request.minecraft_username,
+        server.id,
+        container,
+        request.id
+    );
+
+    if server.required_approvals() > 1 || server.required_denials() > 1 {
+        let count = |vote: &str| votes.iter().filter(|v| v.vote == vote).count();
+        description.push_str(&format!(
+            "\n\n**Approvals ({}/{}):** {}\n**Denials ({}/{}):** {}",
+            count(WhitelistVote::APPROVE),
+            server.required_approvals(),
+            format_voters(votes, WhitelistVote::APPROVE),
+            count(WhitelistVote::DENY),
+            server.required_denials(),
+            format_voters(votes, WhitelistVote::DENY),
+        ));
+    }
+
+    let mut embed = CreateEmbed::new()
+        .title(":bell: Whitelist Request")
+        .color(0xdf8e1d)
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!(
+            "Requested at {}",
+            request.created_at.unwrap_or_else(Utc::now)
+        )));
+
+    // Long answers are cut short so every answer fits
+    let answer_length = match answers.len() {
+        0 => FIELD_VALUE_LENGTH,
+        n => (ANSWERS_LENGTH / n).min(FIELD_VALUE_LENGTH),
+    };
+    for answer in answers {
+        let value = match answer.answer.trim().is_empty() {
+            true => "_No answer_".to_string(),
+            false => utils::truncate(&answer.answer, answer_length),
+        };
+        embed = embed.field(format!("📝 {}", answer.question), value, false);
+    }
+
+    if !conflicts.is_empty() {
+        embed = embed.field(
+            "⚠️ Possible duplicates",
+            requests::format_conflicts(conflicts),
+            false,
+        );
+    }
+
+    embed
+}
+
+fn create_input(question: &FormQuestion) -> CreateInputText {
+    let style = match question.long {
+        true => InputTextStyle::Paragraph,
+        false => InputTextStyle::Short,
+    };
+    let mut input = CreateInputText::new(style, &question.label, "").required(question.required);
+    if let Some(placeholder) = &question.placeholder {
+        input = input.placeholder(placeholder);
+    }
+    if let Some(min_length) = question.min_length {
+        input = input.min_length(min_length);
+    }
+    if let Some(max_length) = question.max_length {
+        input = input.max_length(max_length);
+    }
+
+    input
+}
+
+/// Waits for the requester to click the button with the given ID.
+async fn await_button(ctx: Context<'_>, button_id: String) -> Option<ComponentInteraction> {
+    ComponentInteractionCollector::new(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .custom_ids(vec![button_id])
+        .timeout(FORM_TIMEOUT)
+        .next()
+        .await
+}
+
+/// Asks the server's application form in modals of up to five questions, each opened by a
+/// button, since the command has already been deferred. Returns the answers along with the
+/// interaction of the last page, which has been acknowledged so the request can be answered
+/// through it, or `None` if the requester didn't finish the form in time.
+async fn ask_application_form(
+    ctx: Context<'_>,
+    server: &Server,
+    request_id: &str,
+) -> Result<Option<(Vec<WhitelistAnswer>, ModalInteraction)>, Error> {
+    let pages = server
+        .application_form()
+        .chunks(FormQuestion::PER_PAGE)
+        .collect::<Vec<&[FormQuestion]>>();
+    let mut answers = vec![];
+
+    let button_id = format!("wlform-{request_id}-0");
+    let label = match pages.len() {
+        1 => "Fill out".to_string(),
+        n => format!("Start (1/{n})"),
+    };
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(format!(
+                "📝 Server `{}` asks a few questions before your request is sent.",
+                server.id
+            ))
+            .components(vec![CreateActionRow::Buttons(vec![
+                CreateButton::new(&button_id)
+                    .label(label)
+                    .style(ButtonStyle::Primary),
+            ])]),
+    )
+    .await?;
+    let Some(mut button) = await_button(ctx, button_id).await else {
+        return Ok(None);
+    };
+
+    for (page, questions) in pages.iter().enumerate() {
+        let title = match pages.len() {
+            1 => format!("Apply to {}", server.name),
+            n => format!("Apply to {} ({}/{n})", server.name, page + 1),
+        };
+        let modal = questions.iter().fold(
+            // Modal titles are limited to 45 characters
+            CreateQuickModal::new(utils::truncate(&title, 45)).timeout(FORM_TIMEOUT),
+            |modal, question| modal.field(create_input(question)),
+        );
+
+        let Some(response) = button.quick_modal(ctx.serenity_context(), modal).await? else {
+            return Ok(None);
+        };
+
+        answers.extend(questions.iter().zip(response.inputs).enumerate().map(
+            |(i, (question, answer))| WhitelistAnswer {
+                request_id: request_id.to_string(),
+                position: (page * FormQuestion::PER_PAGE + i) as i32,
+                question: question.label.clone(),
+                answer,
+            },
+        ));
+
+        // Each page replaces the message of the button that opened it, so it can't be clicked again
+        let acknowledgement = match page + 1 == pages.len() {
+            true => CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content("📝 Application submitted!")
+                    .components(vec![]),
+            ),
+            false => {
+                let button_id = format!("wlform-{}-{}", response.interaction.id, page + 1);
+                let message = CreateInteractionResponseMessage::new()
+                    .ephemeral(true)
+                    .content(format!(
+                        "📝 Page {} of {} done, continue with the next questions.",
+                        page + 1,
+                        pages.len()
+                    ))
+                    .button(
+                        CreateButton::new(&button_id)
+                            .label(format!("Continue ({}/{})", page + 2, pages.len()))
+                            .style(ButtonStyle::Primary),
+                    );
+                response
+                    .interaction
+                    .create_response(
+                        ctx.http(),
+                        CreateInteractionResponse::UpdateMessage(message),
+                    )
+                    .await?;
+
+                button = match await_button(ctx, button_id).await {
+                    Some(button) => button,
+                    None => return Ok(None),
+                };
+
+                continue;
+            }
+        };
+
+        response
+            .interaction
+            .create_response(ctx.http(), acknowledgement)
+            .await?;
+
+        return Ok(Some((answers, response.interaction)));
+    }
+
+    Ok(None)
+}
+
 /// Request to be whitelisted on a server
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 async fn request(
     ctx: Context<'_>,
-    #[description = "ID of the target server"]
-    #[autocomplete = "autocomplete_server_ids"]
+    #[description = "ID of the target server or group of servers"]
+    #[autocomplete = "autocomplete_request_targets"]
     server_id: String,
-    #[description = "Your Minecraft username"] minecraft_username: String,
+    #[description = "Your Minecraft username, or gamertag on Bedrock"] minecraft_username: String,
+    #[description = "Edition you play on, defaults to Java"] edition: Option<Edition>,
 ) -> Result<(), Error> {
-    let config = &ctx.data().config;
+    let config = ctx.data().config.load_full();
+    let guild_id = ctx.guild_id().map(|id| id.get());
 
-    if !config.whitelist.skip_username_validation
-        && !crate::utils::validate_minecraft_username(&minecraft_username).await?
-    {
-        return Err(
-            format!(
-				"Invalid Minecraft username `{minecraft_username}`. Please make sure you've entered it correctly."
-			).into(),
-        );
-    }
+    let whitelist_config = config
+        .whitelist_for(guild_id)
+        .ok_or("Whitelist requests aren't set up for this server")?;
 
     let author = ctx.author();
     let author_id = author.id.to_string();
@@ -39,64 +333,740 @@ async fn request(
     let request_id_s = request_id.to_string();
 
     let server = config
-        .servers
-        .iter()
-        .find(|server| server.id == server_id)
+        .request_target(guild_id, &server_id)
         .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::WhitelistRequest, Some(server)).await?;
+
+    // Looking up the player and checking the request can take longer than Discord waits for a
+    // response, so an application form is opened through a button afterwards
+    ctx.defer_ephemeral().await?;
+
+    let minecraft_username = requests::resolve_username(
+        whitelist_config,
+        server,
+        edition.unwrap_or(Edition::Java),
+        &minecraft_username,
+    )
+    .await?;
+
+    let member = ctx.author_member().await.ok_or("Not in a guild")?;
+    let ineligible = requests::check_eligibility(
+        &ctx.data().db,
+        &whitelist_config.eligibility,
+        server,
+        &member,
+        &request_id_s,
+        &minecraft_username,
+    )
+    .await?;
+    if let Some(reason) = ineligible {
+        ctx.send(
+            CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::new()
+                    .title("⛔ You can't request whitelisting")
+                    .description(reason)
+                    .color(0xd20f39),
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let conflicts = requests::find_conflicts(
+        &ctx.data().db,
+        &request_id_s,
+        &author_id,
+        &server.id,
+        &minecraft_username,
+    )
+    .await?;
+    if let Some(repeat) = conflicts.iter().find(|c| c.is_repeat()) {
+        ctx.send(CreateReply::default().ephemeral(true).content(format!(
+            "You already have a pending request for `{}` on server `{}` ({}), please wait for it to be reviewed.",
+            minecraft_username,
+            server.id,
+            repeat.request.link()
+        )))
+        .await?;
+
+        return Ok(());
+    }
+
+    // Everything that can turn the request down is checked before the form is shown, so nobody
+    // fills it out for nothing
+    let (answers, form_interaction) = match server.application_form().is_empty() {
+        true => (vec![], None),
+        false => match ask_application_form(ctx, server, &request_id_s).await? {
+            Some((answers, interaction)) => (answers, Some(interaction)),
+            None => return Ok(()),
+        },
+    };
+
+    let guild_id_s = guild_id.map(|id| id.to_string());
 
     sqlx::query!(
         "
-    	INSERT INTO whitelist_request (id, server_id, discord_id, minecraft_username)
-    	VALUES ( ?, ?, ?, ? )
+    	INSERT INTO whitelist_request (id, server_id, discord_id, minecraft_username, guild_id)
+    	VALUES ( ?, ?, ?, ?, ? )
     	",
         request_id_s,
-        server_id,
+        server.id,
         author_id,
-        minecraft_username
+        minecraft_username,
+        guild_id_s
     )
     .fetch_all(&ctx.data().db)
     .await?;
+    requests::save_answers(&ctx.data().db, &answers).await?;
 
-    let approve_button = CreateButton::new(format!("wlreq-approve-{}", request_id))
-        .label("Approve")
-        .style(ButtonStyle::Success);
+    // Possible duplicates are always left for reviewers
+    let rules = match conflicts.is_empty() {
+        true => server.auto_approve_rules(whitelist_config),
+        false => &[],
+    };
+    let auto_approval = requests::match_auto_approve_rule(
+        &ctx.data().db,
+        &config,
+        server,
+        rules,
+        &member,
+        &minecraft_username,
+    )
+    .await?;
 
-    let deny_button = CreateButton::new(format!("wlreq-deny-{}", request_id))
-        .label("Deny")
-        .style(ButtonStyle::Danger);
+    // Auto-approved requests are queued until the server is running, unless whitelisting fails
+    // outright, in which case they're left for reviewers
+    let status = match &auto_approval {
+        Some(_) => match requests::is_running(&ctx.data().containers, server).await {
+            Ok(true) => {
+                match requests::whitelist_player(&config, server, &minecraft_username).await {
+                    Ok(()) => WhitelistRequest::APPROVED,
+                    Err(err) => {
+                        error!("Failed to auto-approve request `{request_id}`: {err}");
+                        WhitelistRequest::PENDING
+                    }
+                }
+            }
+            _ => WhitelistRequest::QUEUED,
+        },
+        None => WhitelistRequest::PENDING,
+    };
 
-    let request_embed = CreateEmbed::new()
-        .title(":bell: Whitelist Request")
-		.color(0xdf8e1d)
-        .description(format!(
-            "<@{}> has requested to be whitelisted on server _{}_!\n\n**Minecraft Username:** `{}`\n**Server ID:** `{}`\n**Container ID:**: `{}`\n**Request ID:** `{}`",
-            author.id, server.name, minecraft_username, server.id, server.container_id, request_id
+    if status != WhitelistRequest::PENDING {
+        requests::set_status(
+            &ctx.data().db,
+            &request_id_s,
+            WhitelistRequest::PENDING,
+            status,
+            None,
+            auto_approval.as_deref(),
+        )
+        .await?;
+        if status == WhitelistRequest::APPROVED {
+            requests::start_access_timer(&ctx.data().db, &request_id_s, server, None).await?;
+            actions::run_on_approve(
+                ctx.http(),
+                server,
+                guild_id,
+                &author_id,
+                &minecraft_username,
+            )
+            .await;
+        }
+        info!(
+            "Auto-approved request `{request_id}` ({})",
+            auto_approval.as_deref().unwrap_or_default()
+        );
+    }
+
+    let request = WhitelistRequest {
+        id: request_id_s,
+        server_id: server.id.clone(),
+        discord_id: author_id,
+        minecraft_username,
+        created_at: Some(Utc::now()),
+        guild_id: guild_id_s,
+        status: status.to_string(),
+        decided_by: None,
+        decided_at: None,
+        reason: auto_approval.clone(),
+        channel_id: None,
+        message_id: None,
+        expires_at: None,
+        expiry_warned_at: None,
+        revoked_at: None,
+    };
+    let request_embed = create_request_embed(&request, server, &[], &answers, &conflicts);
+
+    // Auto-approved requests are still posted for visibility, but without buttons or pings
+    let message = match &auto_approval {
+        Some(reason) if status != WhitelistRequest::PENDING => {
+            let title = match status {
+                WhitelistRequest::QUEUED => "⏳ Whitelist request auto-approved",
+                _ => "✅ Whitelist request auto-approved",
+            };
+            let mut reason = format!("Matched auto-approve {reason}");
+            if status == WhitelistRequest::QUEUED {
+                reason.push_str("\nThe player will be whitelisted once the server is running.");
+            }
+
+            CreateMessage::new().add_embed(request_embed.title(title).color(0x40a02b).field(
+                "Auto-approved",
+                reason,
+                false,
+            ))
+        }
+        _ => create_request_message(&request, server, whitelist_config, request_embed),
+    };
+
+    let message = ChannelId::new(server.request_channel(whitelist_config))
+        .send_message(ctx.http(), message)
+        .await?;
+    requests::set_message(
+        &ctx.data().db,
+        &request.id,
+        &message.channel_id.to_string(),
+        &message.id.to_string(),
+    )
+    .await?;
+
+    let reply = match status {
+        WhitelistRequest::APPROVED => format!(
+            "Your request was approved automatically and you've been whitelisted on server `{}`!",
+            server.id
+        ),
+        WhitelistRequest::QUEUED => format!(
+            "Your request was approved automatically! You'll be whitelisted on server `{}` once it's running.",
+            server.id
+        ),
+        _ => format!("Sent whitelist request for server `{}`!", server.id),
+    };
+
+    match form_interaction {
+        Some(interaction) => {
+            interaction
+                .create_followup(
+                    ctx.http(),
+                    CreateInteractionResponseFollowup::new()
+                        .ephemeral(true)
+                        .content(reply),
+                )
+                .await?;
+        }
+        None => {
+            ctx.send(CreateReply::default().ephemeral(true).content(reply))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes the status of a request for its requester.
+fn status_label(status: &str) -> &str {
+    match status {
+        WhitelistRequest::PENDING => "⏳ Pending",
+        WhitelistRequest::QUEUED => "🕓 Approved, waiting for the server to start",
+        WhitelistRequest::APPROVED => "✅ Approved",
+        WhitelistRequest::DENIED => "❌ Denied",
+        WhitelistRequest::CANCELLED => "🚫 Cancelled",
+        WhitelistRequest::EXPIRED => "⌛ Expired",
+        WhitelistRequest::REVOKED => "🔒 Access removed",
+        WhitelistRequest::REVOKING => "🔒 Access being removed",
+        status => status,
+    }
+}
+
+async fn autocomplete_pending_requests(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    let author_id = ctx.author().id.to_string();
+    let requests = sqlx::query_as!(
+        WhitelistRequest,
+        "
+        SELECT *
+        FROM whitelist_request
+        WHERE discord_id = ? AND status = ?
+        ORDER BY created_at DESC
+        ",
+        author_id,
+        WhitelistRequest::PENDING
+    )
+    .fetch_all(&ctx.data().db)
+    .await
+    .unwrap_or_else(|err| {
+        error!("Failed to autocomplete pending requests: {err}");
+        vec![]
+    });
+
+    let partial = partial.to_lowercase();
+    requests
+        .into_iter()
+        .filter(|r| {
+            r.id.starts_with(&partial) || r.minecraft_username.to_lowercase().starts_with(&partial)
+        })
+        // Discord shows at most 25 choices
+        .take(25)
+        .map(|r| {
+            AutocompleteChoice::new(
+                format!("{} on {} ({})", r.minecraft_username, r.server_id, r.id),
+                r.id,
+            )
+        })
+        .collect::<Vec<AutocompleteChoice>>()
+        .into_iter()
+}
+
+/// Show the status of your whitelist requests
+#[poise::command(slash_command, guild_only)]
+async fn status(ctx: Context<'_>) -> Result<(), Error> {
+    const SHOWN: i64 = 10;
+
+    let config = ctx.data().config.load_full();
+    let author_id = ctx.author().id.to_string();
+
+    let requests = sqlx::query_as!(
+        WhitelistRequest,
+        "
+        SELECT *
+        FROM whitelist_request
+        WHERE discord_id = ?
+        ORDER BY created_at DESC
+        LIMIT ?
+        ",
+        author_id,
+        SHOWN
+    )
+    .fetch_all(&ctx.data().db)
+    .await?;
+
+    if requests.is_empty() {
+        ctx.send(CreateReply::default().ephemeral(true).content(
+            "You haven't requested to be whitelisted yet, use `/whitelist request` to do so!",
         ))
-		.footer(CreateEmbedFooter::new(format!("Requested at {}", Utc::now())));
+        .await?;
 
-    let pings = config
-        .whitelist
-        .ping_roles
+        return Ok(());
+    }
+
+    let lines = requests
         .iter()
-        .map(|role_id| format!("<@&{}>", role_id))
+        .map(|request| {
+            let server_name = config
+                .servers
+                .iter()
+                .find(|s| s.id == request.server_id)
+                .map(|s| s.name.as_str())
+                .unwrap_or(&request.server_id);
+            let requested_at = request
+                .created_at
+                .map(|t| format!(" <t:{}:R>", t.timestamp()))
+                .unwrap_or_default();
+            let access_ends = match (request.status.as_str(), request.expires_at) {
+                (WhitelistRequest::APPROVED | WhitelistRequest::QUEUED, Some(expires_at)) => {
+                    format!("\n-# Access ends <t:{}:R>", expires_at.timestamp())
+                }
+                _ => String::new(),
+            };
+
+            format!(
+                "{} **`{}`** on _{}_{requested_at}{access_ends}\n-# Request ID: `{}`",
+                status_label(&request.status),
+                request.minecraft_username,
+                server_name,
+                request.id
+            )
+        })
         .collect::<Vec<String>>()
-        .join(" ");
+        .join("\n\n");
 
-    let message = CreateMessage::new()
-        .add_embed(request_embed)
-        .content(pings)
-        .button(approve_button)
-        .button(deny_button);
+    let embed = CreateEmbed::new()
+        .title("📋 Your whitelist requests")
+        .color(0x04a5e5)
+        .description(lines)
+        .footer(CreateEmbedFooter::new(
+            "Use /whitelist cancel or /whitelist edit to change a pending request",
+        ));
 
-    ChannelId::new(config.whitelist.request_channel)
-        .send_message(ctx.http(), message)
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
         .await?;
 
+    Ok(())
+}
+
+/// Withdraw one of your pending whitelist requests
+#[poise::command(slash_command, guild_only)]
+async fn cancel(
+    ctx: Context<'_>,
+    #[description = "Request to cancel"]
+    #[autocomplete = "autocomplete_pending_requests"]
+    request_id: String,
+) -> Result<(), Error> {
+    let db = &ctx.data().db;
+    let author_id = ctx.author().id.to_string();
+
+    let request = requests::fetch_own_request(db, &request_id, &author_id)
+        .await?
+        .ok_or(format!("Request `{}` not found", request_id))?;
+    if request.status != WhitelistRequest::PENDING {
+        return Err(format!(
+            "Request `{}` can't be cancelled anymore since it's {}",
+            request.id, request.status
+        )
+        .into());
+    }
+
+    let cancelled = requests::set_status(
+        db,
+        &request.id,
+        WhitelistRequest::PENDING,
+        WhitelistRequest::CANCELLED,
+        None,
+        Some("Cancelled by the requester"),
+    )
+    .await?;
+    if !cancelled {
+        return Err(format!(
+            "Request `{}` can't be cancelled anymore since it was just decided",
+            request.id
+        )
+        .into());
+    }
+    info!("Request `{}` was cancelled by its requester", request.id);
+
+    let embed = CreateEmbed::new()
+        .title("🚫 Whitelist request cancelled")
+        .color(0x8c8fa1)
+        .description(format!(
+            "<@{}> cancelled their whitelist request.\n\n**Request ID:** {}\n**Server ID:** {}\n**Minecraft Username:** {}",
+            request.discord_id, request.id, request.server_id, request.minecraft_username
+        ))
+        .footer(CreateEmbedFooter::new(format!(
+            "Cancelled at {}",
+            Utc::now()
+        )));
+    let message = EditMessage::new()
+        .content("")
+        .embed(embed)
+        .components(vec![]);
+    if let Err(err) = requests::edit_message(ctx.http(), &request, message).await {
+        warn!(
+            "Failed to update the message of cancelled request `{}`: {err}",
+            request.id
+        );
+    }
+
     ctx.send(CreateReply::default().ephemeral(true).content(format!(
-        "Sent whitelist request for server `{}`!",
-        server.id
+        "Cancelled your request for `{}` on server `{}`.",
+        request.minecraft_username, request.server_id
     )))
     .await?;
 
     Ok(())
 }
+
+/// Correct the Minecraft username of a request that hasn't been reviewed yet
+#[poise::command(slash_command, guild_only)]
+async fn edit(
+    ctx: Context<'_>,
+    #[description = "Request to edit"]
+    #[autocomplete = "autocomplete_pending_requests"]
+    request_id: String,
+    #[description = "Your Minecraft username"] minecraft_username: String,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let db = &ctx.data().db;
+    let guild_id = ctx.guild_id().map(|id| id.get());
+    let author_id = ctx.author().id.to_string();
+
+    let whitelist_config = config
+        .whitelist_for(guild_id)
+        .ok_or("Whitelist requests aren't set up for this server")?;
+
+    let mut request = requests::fetch_own_request(db, &request_id, &author_id)
+        .await?
+        .ok_or(format!("Request `{}` not found", request_id))?;
+    if request.status != WhitelistRequest::PENDING {
+        return Err(format!(
+            "Request `{}` can't be edited anymore since it's {}",
+            request.id, request.status
+        )
+        .into());
+    }
+    let votes = requests::fetch_votes(db, &request.id).await?;
+    if !votes.is_empty() {
+        return Err(format!(
+            "Request `{}` can't be edited anymore since reviewers have already voted on it",
+            request.id
+        )
+        .into());
+    }
+
+    let server = config
+        .servers_for(guild_id)
+        .into_iter()
+        .find(|server| server.id == request.server_id)
+        .ok_or(format!("Server with ID `{}` not found", request.server_id))?;
+
+    // The edition stays the same
+    let minecraft_username = requests::resolve_username(
+        whitelist_config,
+        server,
+        Edition::of(server, &request.minecraft_username),
+        &minecraft_username,
+    )
+    .await?;
+
+    // The member may have lost a required role, or the new account may be blocked or over the
+    // limit, since the request was made
+    let member = ctx.author_member().await.ok_or("Not in a guild")?;
+    let ineligible = requests::check_eligibility(
+        db,
+        &whitelist_config.eligibility,
+        server,
+        &member,
+        &request.id,
+        &minecraft_username,
+    )
+    .await?;
+    if let Some(reason) = ineligible {
+        ctx.send(
+            CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::new()
+                    .title("⛔ You can't request whitelisting")
+                    .description(reason)
+                    .color(0xd20f39),
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let conflicts =
+        requests::find_conflicts(db, &request.id, &author_id, &server.id, &minecraft_username)
+            .await?;
+    if let Some(repeat) = conflicts.iter().find(|c| c.is_repeat()) {
+        return Err(format!(
+            "You already have a pending request for `{}` on server `{}` ({})",
+            minecraft_username,
+            server.id,
+            repeat.request.link()
+        )
+        .into());
+    }
+
+    requests::set_username(db, &request.id, &minecraft_username).await?;
+    info!(
+        "Request `{}` was changed from `{}` to `{}` by its requester",
+        request.id, request.minecraft_username, minecraft_username
+    );
+    let old_username = std::mem::replace(&mut request.minecraft_username, minecraft_username);
+
+    let answers = requests::fetch_answers(db, &request.id).await?;
+    let embed = create_request_embed(&request, server, &votes, &answers, &conflicts).field(
+        "✏️ Edited",
+        format!("Minecraft username changed from `{old_username}`"),
+        false,
+    );
+    if let Err(err) =
+        requests::edit_message(ctx.http(), &request, EditMessage::new().embed(embed)).await
+    {
+        warn!(
+            "Failed to update the message of edited request `{}`: {err}",
+            request.id
+        );
+    }
+
+    ctx.send(CreateReply::default().ephemeral(true).content(format!(
+        "Changed the Minecraft username of your request for server `{}` to `{}`.",
+        server.id, request.minecraft_username
+    )))
+    .await?;
+
+    Ok(())
+}
+
+/// Give a member temporary access to a server
+#[poise::command(slash_command, guild_only)]
+async fn grant(
+    ctx: Context<'_>,
+    #[description = "Member to give access to"] user: serenity::User,
+    #[description = "ID of the target server"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: String,
+    #[description = "The member's Minecraft username, or gamertag on Bedrock"]
+    minecraft_username: String,
+    #[description = "How long the access lasts, e.g. 12h, 3d or 1w, defaults to the server's"]
+    duration: Option<String>,
+    #[description = "Edition the member plays on, defaults to Java"] edition: Option<Edition>,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let db = &ctx.data().db;
+    let guild_id = ctx.guild_id().map(|id| id.get());
+
+    let whitelist_config = config
+        .whitelist_for(guild_id)
+        .ok_or("Whitelist requests aren't set up for this server")?;
+
+    let server = config
+        .servers_for(guild_id)
+        .into_iter()
+        .find(|server| server.id == server_id)
+        .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::WhitelistReview, Some(server)).await?;
+
+    let duration = match duration {
+        Some(duration) => duration,
+        None => match server.access_hours() {
+            Some(hours) => format!("{hours}h"),
+            None => {
+                return Err(format!(
+                    "Server `{}` has no default `access_hours`, please give a duration",
+                    server.id
+                )
+                .into());
+            }
+        },
+    };
+    let expires_at = Utc::now()
+        + utils::parse_duration(&duration).ok_or(format!(
+            "Invalid duration `{duration}`, use something like `90m`, `12h`, `3d` or `1w`"
+        ))?;
+
+    let minecraft_username = requests::resolve_username(
+        whitelist_config,
+        server,
+        edition.unwrap_or(Edition::Java),
+        &minecraft_username,
+    )
+    .await?;
+
+    // Players are only whitelisted once the server is running, like auto-approved requests
+    let status = match requests::is_running(&ctx.data().containers, server).await? {
+        true => {
+            requests::whitelist_player(&config, server, &minecraft_username).await?;
+            WhitelistRequest::APPROVED
+        }
+        false => WhitelistRequest::QUEUED,
+    };
+
+    let request_id = Uuid::new_v4().to_string();
+    let discord_id = user.id.to_string();
+    let staff_id = ctx.author().id.to_string();
+    let guild_id_s = guild_id.map(|id| id.to_string());
+    let reason = format!("Temporary access for {duration} granted by staff");
+
+    sqlx::query!(
+        "
+        INSERT INTO whitelist_request
+            (id, server_id, discord_id, minecraft_username, guild_id, status, decided_by, decided_at, reason, expires_at)
+        VALUES ( ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, ?, ? )
+        ",
+        request_id,
+        server.id,
+        discord_id,
+        minecraft_username,
+        guild_id_s,
+        status,
+        staff_id,
+        reason,
+        expires_at
+    )
+    .execute(db)
+    .await?;
+    info!(
+        "{} granted `{minecraft_username}` access to `{}` until {expires_at} (request `{request_id}`)",
+        ctx.author().name,
+        server.id
+    );
+
+    if status == WhitelistRequest::APPROVED {
+        actions::run_on_approve(
+            ctx.http(),
+            server,
+            guild_id,
+            &discord_id,
+            &minecraft_username,
+        )
+        .await;
+    }
+    if status == WhitelistRequest::APPROVED && whitelist_config.send_approval_dm {
+        if let Some(request) = requests::fetch_own_request(db, &request_id, &discord_id).await? {
+            let footer = requests::fetch_dm_footer(ctx.http(), guild_id).await;
+            requests::send_approval_dm(ctx.http(), &request, server, footer).await;
+        }
+    }
+
+    let reply = match status {
+        WhitelistRequest::APPROVED => format!(
+            "Whitelisted `{minecraft_username}` for <@{discord_id}> on server `{}` until <t:{}:f>.",
+            server.id,
+            expires_at.timestamp()
+        ),
+        _ => format!(
+            "`{minecraft_username}` will be whitelisted for <@{discord_id}> once server `{}` is running, until <t:{}:f>.",
+            server.id,
+            expires_at.timestamp()
+        ),
+    };
+    ctx.send(CreateReply::default().ephemeral(true).content(reply))
+        .await?;
+
+    Ok(())
+}
+
+/// Compare a server's whitelist with who has its whitelist role
+#[poise::command(slash_command, guild_only)]
+async fn sync(
+    ctx: Context<'_>,
+    #[description = "ID of the server to sync"]
+    #[autocomplete = "autocomplete_server_ids"]
+    server_id: String,
+    #[description = "Apply the changes instead of only showing them"] apply: Option<bool>,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+
+    let server = config
+        .servers_for(Some(guild_id.get()))
+        .into_iter()
+        .find(|server| server.id == server_id)
+        .ok_or(format!("Server with ID `{}` not found", server_id))?;
+    permissions::require(ctx, Permission::ConfigManage, Some(server)).await?;
+
+    let role_id = server.whitelist_role().ok_or(format!(
+        "Server `{}` doesn't have a `whitelist_role`",
+        server.id
+    ))?;
+    let role_in_guild = ctx
+        .guild()
+        .is_some_and(|g| g.roles.contains_key(&serenity::RoleId::new(role_id)));
+    if !role_in_guild {
+        return Err(format!(
+            "The whitelist role of server `{}` doesn't belong to this Discord server",
+            server.id
+        )
+        .into());
+    }
+
+    ctx.defer_ephemeral().await?;
+
+    let diff =
+        role_sync::compute_diff(ctx.http(), &config, &ctx.data().db, server, guild_id).await?;
+    let apply = apply.unwrap_or(false) && !diff.is_empty();
+    if apply {
+        role_sync::apply_diff(ctx.http(), &config, &ctx.data().db, server, guild_id, &diff).await?;
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .embed(diff.to_embed(server, role_id, apply))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use bollard::{
+    Docker, container::ListContainersOptions, errors::Error as DockerError,
+    secret::ContainerInspectResponse,
+};
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use crate::{
+    error::ApplicationError,
+    models::config::{ContainerRef, Server},
+};
+
+/// Resolves the container backing each server, caching the resolved container IDs.
+///
+/// Containers referenced by name or by compose project + service get a new ID whenever they're
+/// recreated, so a cached ID is dropped and looked up again when Docker no longer knows it. IDs
+/// are cached by reference rather than by server, so changing a server's container takes effect
+/// right away.
+#[derive(Debug, Default)]
+pub struct ContainerResolver {
+    cache: RwLock<HashMap<ContainerRef, String>>,
+}
+
+impl ContainerResolver {
+    /// Returns the container ID for the given server, looking it up if it isn't cached yet.
+    pub async fn resolve(
+        &self,
+        docker: &Docker,
+        server: &Server,
+    ) -> Result<String, ApplicationError> {
+        let container_ref = server
+            .container_ref()
+            .ok_or_else(|| ApplicationError::ContainerNotConfigured(server.id.clone()))?;
+
+        if let ContainerRef::Id(id) = container_ref {
+            return Ok(id);
+        }
+
+        if let Some(id) = self.cache.read().await.get(&container_ref) {
+            return Ok(id.clone());
+        }
+
+        let id = find_container(docker, &container_ref).await?;
+        debug!(
+            "Resolved container `{container_ref}` for server `{}` to `{id}`",
+            server.id
+        );
+
+        self.cache.write().await.insert(container_ref, id.clone());
+
+        Ok(id)
+    }
+
+    /// Inspects the container for the given server, re-resolving it once if the cached ID is gone.
+    pub async fn inspect(
+        &self,
+        docker: &Docker,
+        server: &Server,
+    ) -> Result<ContainerInspectResponse, ApplicationError> {
+        let id = self.resolve(docker, server).await?;
+
+        match (
+            docker.inspect_container(&id, None).await,
+            server.container_ref(),
+        ) {
+            (
+                Err(DockerError::DockerResponseServerError {
+                    status_code: 404, ..
+                }),
+                Some(container_ref),
+            ) if !matches!(container_ref, ContainerRef::Id(_)) => {
+                self.invalidate(&container_ref).await;
+                let id = self.resolve(docker, server).await?;
+                Ok(docker.inspect_container(&id, None).await?)
+            }
+            (result, _) => Ok(result?),
+        }
+    }
+
+    /// Forgets the cached container ID for the given reference.
+    pub async fn invalidate(&self, container_ref: &ContainerRef) {
+        self.cache.write().await.remove(container_ref);
+    }
+}
+
+/// Looks up a container by name or compose labels, preferring running containers and then the
+/// most recently created one.
+async fn find_container(
+    docker: &Docker,
+    container_ref: &ContainerRef,
+) -> Result<String, ApplicationError> {
+    let filters = match container_ref {
+        ContainerRef::Id(id) => return Ok(id.clone()),
+        ContainerRef::Name(name) => HashMap::from([("name".to_string(), vec![name.clone()])]),
+        ContainerRef::Compose { project, service } => HashMap::from([(
+            "label".to_string(),
+            vec![
+                format!("com.docker.compose.project={project}"),
+                format!("com.docker.compose.service={service}"),
+            ],
+        )]),
+    };
+
+    let mut containers = docker
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters,
+            ..Default::default()
+        }))
+        .await?;
+
+    // The name filter matches substrings, so only keep exact matches
+    if let ContainerRef::Name(name) = container_ref {
+        containers.retain(|c| {
+            c.names
+                .iter()
+                .flatten()
+                .any(|n| n.trim_start_matches('/') == name)
+        });
+    }
+
+    containers
+        .into_iter()
+        .max_by_key(|c| (c.state.as_deref() == Some("running"), c.created))
+        .and_then(|c| c.id)
+        .ok_or_else(|| ApplicationError::ContainerNotFound(container_ref.to_string()))
+}
@@ -1,6 +1,6 @@
 use poise::{CreateReply, FrameworkError, serenity_prelude::CreateEmbed};
 
-use crate::{Data, Error};
+use crate::{Data, Error, permissions::Permission};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ApplicationError {
@@ -16,6 +16,18 @@ pub enum ApplicationError {
     SqlMigrate(#[from] sqlx::migrate::MigrateError),
     #[error("Failed to deserialize toml: {0}")]
     TomlDeserialize(#[from] toml::de::Error),
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("Failed to watch config file: {0}")]
+    Notify(#[from] notify::Error),
+    #[error("Docker error: {0}")]
+    Docker(#[from] bollard::errors::Error),
+    #[error("No container configured for server `{0}`")]
+    ContainerNotConfigured(String),
+    #[error("No container found for `{0}`")]
+    ContainerNotFound(String),
+    #[error("You don't have the `{0}` permission")]
+    MissingPermission(Permission),
 }
 
 pub async fn error_handler(error: FrameworkError<'_, Data, Error>) {
@@ -35,6 +47,20 @@ pub async fn error_handler(error: FrameworkError<'_, Data, Error>) {
                 .send(CreateReply::default().embed(embed).ephemeral(true))
                 .await;
         }
+        FrameworkError::CommandCheckFailed { error, ctx, .. } => {
+            let description = match error {
+                Some(error) => error.to_string(),
+                None => "You're not allowed to use this command".to_string(),
+            };
+            let embed = CreateEmbed::default()
+                .title("⛔ Missing permission")
+                .color(0xd20f39)
+                .description(description);
+
+            let _ = ctx
+                .send(CreateReply::default().embed(embed).ephemeral(true))
+                .await;
+        }
         error => {
             if let Err(e) = poise::builtins::on_error(error).await {
                 tracing::error!("{}", e);
@@ -1,52 +1,77 @@
+use std::time::Duration;
+
 use bollard::{Docker, secret::ContainerStateStatusEnum};
 use chrono::Utc;
 use poise::serenity_prelude::{self as serenity, EditMessage};
 use serenity::{
-    CacheHttp, Context, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
-    CreateInteractionResponseFollowup, CreateMessage, FullEvent, UserId,
+    CacheHttp, Context, CreateEmbed, CreateEmbedFooter, CreateInputText, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateMessage, CreateQuickModal, FullEvent, InputTextStyle,
+    UserId,
 };
 use tracing::{error, info};
 
-use crate::{Data, Error, models::database::WhitelistRequest, utils};
+/// How long a reviewer has to enter the duration of a timed approval.
+const DURATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+use crate::{
+    Data, Error, access, actions,
+    commands::servers::whitelist::{create_request_embed, format_voters},
+    error::ApplicationError,
+    models::database::{WhitelistRequest, WhitelistVote},
+    permissions::{self, Permission},
+    requests::{self, create_dm_footer},
+    sync, utils,
+};
+
+/// The interaction a button press is answered through: the press itself, or the modal it opened,
+/// which has been acknowledged already.
+#[derive(Clone, Copy)]
+enum Responder<'a> {
+    Button(&'a serenity::ComponentInteraction),
+    Modal(&'a serenity::ModalInteraction),
+}
 
 async fn create_interaction_followup(
     ctx: &Context,
-    component_interaction: &serenity::ComponentInteraction,
+    responder: Responder<'_>,
     title: &str,
     description: &str,
     color: u32,
     ephemeral: bool,
 ) -> Result<(), Error> {
     let http = ctx.http();
-    component_interaction
-        .create_response(http, CreateInteractionResponse::Acknowledge)
-        .await?;
-    component_interaction
-        .create_followup(
-            http,
-            CreateInteractionResponseFollowup::new()
-                .add_embed(
-                    CreateEmbed::new()
-                        .title(title)
-                        .description(description)
-                        .color(color),
-                )
-                .ephemeral(ephemeral),
+    let followup = CreateInteractionResponseFollowup::new()
+        .add_embed(
+            CreateEmbed::new()
+                .title(title)
+                .description(description)
+                .color(color),
         )
-        .await?;
+        .ephemeral(ephemeral);
+    match responder {
+        Responder::Button(interaction) => {
+            interaction
+                .create_response(http, CreateInteractionResponse::Acknowledge)
+                .await?;
+            interaction.create_followup(http, followup).await?;
+        }
+        Responder::Modal(interaction) => {
+            interaction.create_followup(http, followup).await?;
+        }
+    }
 
     Ok(())
 }
 
 async fn create_error_followup(
     ctx: &Context,
-    component_interaction: &serenity::ComponentInteraction,
+    responder: Responder<'_>,
     error_title: &str,
     error_description: &str,
 ) -> Result<(), Error> {
     create_interaction_followup(
         ctx,
-        component_interaction,
+        responder,
         &format!("⁉️ Error: {error_title}"),
         error_description,
         0xd20f39,
@@ -57,25 +82,88 @@ async fn create_error_followup(
     Ok(())
 }
 
-fn create_dm_footer(guild_name: String, guild_icon: Option<String>) -> CreateEmbedFooter {
-    CreateEmbedFooter::new(format!("From server {guild_name}")).icon_url(
-        guild_icon.unwrap_or("https://files.jadelily.dev/ZabtFsxYPYvgO2bpKry3.png".to_string()),
-    )
-}
-
 pub async fn event_handler(
     ctx: &Context,
     event: &FullEvent,
     _framework: poise::FrameworkContext<'_, Data, Error>,
     data: &Data,
 ) -> Result<(), Error> {
+    match event {
+        FullEvent::GuildMemberRemoval { guild_id, user, .. } => {
+            let config = data.config.load_full();
+            access::revoke_member(
+                ctx.http(),
+                &config,
+                &data.db,
+                guild_id.get(),
+                user,
+                "left the Discord server",
+            )
+            .await?;
+        }
+        FullEvent::GuildBanAddition {
+            guild_id,
+            banned_user,
+        } => {
+            let config = data.config.load_full();
+            access::revoke_member(
+                ctx.http(),
+                &config,
+                &data.db,
+                guild_id.get(),
+                banned_user,
+                "was banned from the Discord server",
+            )
+            .await?;
+        }
+        FullEvent::GuildMemberUpdate {
+            old_if_available,
+            event: member_update,
+            ..
+        } if !member_update.user.bot => {
+            // Only changes to whitelist roles matter, not nicknames or unrelated roles
+            let config = data.config.load_full();
+            if data
+                .role_sync
+                .touches_whitelist_role(
+                    &config,
+                    member_update.guild_id,
+                    member_update.user.id,
+                    old_if_available.as_ref().map(|old| old.roles.as_slice()),
+                    &member_update.roles,
+                )
+                .await
+            {
+                sync::sync_member(
+                    ctx,
+                    &config,
+                    &data.db,
+                    &data.role_sync,
+                    member_update.guild_id,
+                    member_update.user.id,
+                    &member_update.roles,
+                )
+                .await?;
+            }
+        }
+        _ => {}
+    }
+
     if let FullEvent::InteractionCreate { interaction } = event {
         if let Some(component_interaction) = interaction.as_message_component() {
             let id = component_interaction.data.custom_id.clone();
             let mut message = component_interaction.message.clone();
 
-            if id.starts_with("wlreq-") {
-                let config = &data.config;
+            // Request IDs contain dashes themselves, so only the first one splits off the action
+            if let Some((action, request_id)) = id
+                .strip_prefix("wlreq-")
+                .and_then(|rest| rest.split_once('-'))
+            {
+                let request_id = request_id.to_string();
+
+                let responder = Responder::Button(component_interaction);
+
+                let config = data.config.load_full();
                 let guild_id = match component_interaction.guild_id {
                     Some(guild_id) => guild_id,
                     None => {
@@ -83,53 +171,25 @@ pub async fn event_handler(
                     }
                 };
 
-                // let guild_icon = guild_id.get_preview(&ctx).await?.icon;
-                let guild_icon = guild_id.to_partial_guild(&ctx).await?.icon_url();
-                let guild_name = guild_id.name(ctx).unwrap();
-
-                let interaction_author = &component_interaction.user;
-
-                let mut is_user_authorized = false;
-
-                for role_id in config.whitelist.allowed_roles.clone() {
-                    let has_role = interaction_author
-                        .has_role(&ctx.http, guild_id, role_id)
+                let whitelist_config = match config.whitelist_for(Some(guild_id.get())) {
+                    Some(whitelist_config) => whitelist_config,
+                    None => {
+                        create_error_followup(
+                            ctx,
+                            responder,
+                            "Whitelist not configured!",
+                            "Whitelist requests aren't set up for this server",
+                        )
                         .await?;
 
-                    if has_role {
-                        is_user_authorized = true;
-                        break;
-                    }
-                }
-
-                if let Some(member) = interaction_author.member.as_deref() {
-                    if let Some(permissions) = member.permissions {
-                        if config.whitelist.allow_admin && permissions.administrator() {
-                            is_user_authorized = true;
-                        }
+                        return Ok(());
                     }
                 };
 
-                if !is_user_authorized {
-                    create_error_followup(
-                        ctx,
-                        component_interaction,
-                        "Unauthorized!",
-                        "You are not authorized to perform this action",
-                    )
-                    .await?;
-
-                    return Ok(());
-                }
-
-                let request_id = match id.contains("approve") {
-                    true => id.replace("wlreq-approve-", ""),
-                    false => id.replace("wlreq-deny-", ""),
-                };
-
                 let exists = sqlx::query!(
-                    "SELECT EXISTS(SELECT 1 FROM whitelist_request WHERE id = ?) as 'exists'",
-                    request_id
+                    "SELECT EXISTS(SELECT 1 FROM whitelist_request WHERE id = ? AND status = ?) as 'exists'",
+                    request_id,
+                    WhitelistRequest::PENDING
                 )
                 .fetch_one(&data.db)
                 .await?
@@ -137,7 +197,7 @@ pub async fn event_handler(
                     > 0;
 
                 if exists {
-                    let request_info: WhitelistRequest = sqlx::query_as!(
+                    let mut request_info: WhitelistRequest = sqlx::query_as!(
                         WhitelistRequest,
                         "
 							SELECT *
@@ -149,16 +209,19 @@ pub async fn event_handler(
                     .fetch_one(&data.db)
                     .await?;
 
+                    // Requests from other guilds are treated as unknown, even if a button ID
+                    // names them
                     let server = match config
-                        .servers
-                        .iter()
+                        .servers_for(Some(guild_id.get()))
+                        .into_iter()
                         .find(|s| s.id == request_info.server_id)
+                        .filter(|_| requests::made_in_guild(&config, &request_info, guild_id.get()))
                     {
                         Some(server) => server,
                         None => {
                             create_error_followup(
                                 ctx,
-                                component_interaction,
+                                responder,
                                 "Server not found!",
                                 &format!(
                                     "Server with the ID `{}` not found",
@@ -171,33 +234,128 @@ pub async fn event_handler(
                         }
                     };
 
-                    if server.container_id.is_empty() {
+                    let is_user_authorized =
+                        component_interaction.member.as_ref().is_some_and(|member| {
+                            permissions::has_permission(
+                                &config,
+                                member,
+                                Permission::WhitelistReview,
+                                Some(server),
+                            )
+                        });
+
+                    if !is_user_authorized {
                         create_error_followup(
                             ctx,
-                            component_interaction,
-                            "Container ID not found!",
-                            &format!("Container ID not found for server `{}`. Please add one in your `config.toml` file.", server.id),
+                            responder,
+                            "Unauthorized!",
+                            "You are not authorized to perform this action",
                         )
                         .await?;
 
-                        return Err(anyhow::anyhow!("Container ID not found").into());
+                        return Ok(());
                     }
 
-                    let container_status = match Docker::connect_with_defaults()?
-                        .inspect_container(&server.container_id, None)
-                        .await?
-                        .state
+                    // The modal has to be the first response to the button, so only the request
+                    // and the reviewer's permission are checked before it's shown
+                    let modal_response = match action {
+                        "approvefor" => {
+                            let modal = CreateQuickModal::new("Approve for a set time")
+                                .timeout(DURATION_TIMEOUT)
+                                .field(
+                                    CreateInputText::new(InputTextStyle::Short, "Duration", "")
+                                        .placeholder(
+                                            "e.g. 12h, 3d or 1w, leave empty for the server's default",
+                                        )
+                                        .required(false),
+                                );
+                            let Some(response) =
+                                component_interaction.quick_modal(ctx, modal).await?
+                            else {
+                                return Ok(());
+                            };
+                            response
+                                .interaction
+                                .create_response(ctx.http(), CreateInteractionResponse::Acknowledge)
+                                .await?;
+                            Some(response)
+                        }
+                        _ => None,
+                    };
+                    let responder = match &modal_response {
+                        Some(response) => Responder::Modal(&response.interaction),
+                        None => responder,
+                    };
+
+                    let duration = match modal_response
+                        .as_ref()
+                        .map(|response| response.inputs[0].trim())
                     {
+                        None | Some("") => None,
+                        Some(input) => match utils::parse_duration(input) {
+                            Some(duration) => Some(duration),
+                            None => {
+                                create_error_followup(
+                                    ctx,
+                                    responder,
+                                    "Invalid duration!",
+                                    &format!(
+                                        "`{input}` isn't a duration, use something like `90m`, `12h`, `3d` or `1w`"
+                                    ),
+                                )
+                                .await?;
+
+                                return Ok(());
+                            }
+                        },
+                    };
+
+                    // let guild_icon = guild_id.get_preview(&ctx).await?.icon;
+                    let guild_icon = guild_id.to_partial_guild(&ctx).await?.icon_url();
+                    let guild_name = guild_id.name(ctx).unwrap();
+
+                    let docker = Docker::connect_with_defaults()?;
+                    let container = match data.containers.inspect(&docker, server).await {
+                        Ok(container) => container,
+                        Err(ApplicationError::ContainerNotConfigured(_)) => {
+                            create_error_followup(
+                                ctx,
+                                responder,
+                                "Container not configured!",
+                                &format!("No container configured for server `{}`. Please add a `container_id`, `container_name` or `compose_project` and `compose_service` in your `config.toml` file.", server.id),
+                            )
+                            .await?;
+
+                            return Err(anyhow::anyhow!("Container not configured").into());
+                        }
+                        Err(ApplicationError::ContainerNotFound(container_ref)) => {
+                            create_error_followup(
+                                ctx,
+                                responder,
+                                "Container not found!",
+                                &format!(
+                                    "No Docker container found for `{}` (server `{}`)",
+                                    container_ref, server.id
+                                ),
+                            )
+                            .await?;
+
+                            return Err(anyhow::anyhow!("Container not found").into());
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+
+                    let container_status = match container.state {
                         Some(state) => match state.status {
                             Some(status) => status,
                             None => {
                                 create_error_followup(
                                     ctx,
-                                    component_interaction,
+                                    responder,
                                     "Failed to get server status!",
                                     &format!(
-										"Failed to get server status from Docker container with the ID `{}`!",
-										server.container_id
+										"Failed to get server status from Docker container for server `{}`!",
+										server.id
 									),
                                 )
                                 .await?;
@@ -211,11 +369,11 @@ pub async fn event_handler(
                         None => {
                             create_error_followup(
                                 ctx,
-                                component_interaction,
+                                responder,
                                 "Failed to get server state!",
                                 &format!(
-                                    "Failed to get server state from Docker container with the ID `{}`",
-                                    server.container_id
+                                    "Failed to get server state from Docker container for server `{}`",
+                                    server.id
                                 ),
                             )
                             .await?;
@@ -230,55 +388,148 @@ pub async fn event_handler(
                     let requester_id = request_info.discord_id.parse::<u64>().unwrap();
                     let user = UserId::new(requester_id);
 
-                    if id.contains("approve") {
-                        if container_status == ContainerStateStatusEnum::RUNNING {
-                            let mut rcon_client = utils::create_rcon_client(
-                                &server.address,
-                                server.rcon_port,
-                                server.rcon_password.clone(),
+                    let reviewer_id = component_interaction.user.id.to_string();
+                    let mut votes = requests::fetch_votes(&data.db, &request_id).await?;
+
+                    if votes.iter().any(|v| v.reviewer_id == reviewer_id) {
+                        create_error_followup(
+                            ctx,
+                            responder,
+                            "Already voted!",
+                            "You've already voted on this request",
+                        )
+                        .await?;
+
+                        return Ok(());
+                    }
+
+                    let vote = match action {
+                        "deny" => WhitelistVote::DENY,
+                        _ => WhitelistVote::APPROVE,
+                    };
+                    votes.push(WhitelistVote {
+                        request_id: request_id.clone(),
+                        reviewer_id: reviewer_id.clone(),
+                        vote: vote.to_string(),
+                        created_at: None,
+                        duration_minutes: duration.map(|duration| duration.num_minutes()),
+                    });
+
+                    let (approvals, denials) = requests::tally(&votes);
+                    let approved = approvals >= server.required_approvals();
+                    let denied = denials >= server.required_denials();
+
+                    if !approved && !denied {
+                        requests::record_vote(&data.db, &request_id, &reviewer_id, vote, duration)
+                            .await?;
+
+                        let conflicts = requests::find_conflicts(
+                            &data.db,
+                            &request_info.id,
+                            &request_info.discord_id,
+                            &request_info.server_id,
+                            &request_info.minecraft_username,
+                        )
+                        .await?;
+                        let answers = requests::fetch_answers(&data.db, &request_info.id).await?;
+                        message
+                            .edit(
+                                &ctx,
+                                EditMessage::new().embed(create_request_embed(
+                                    &request_info,
+                                    server,
+                                    &votes,
+                                    &answers,
+                                    &conflicts,
+                                )),
                             )
                             .await?;
 
-                            rcon_client
-                                .run_command(&format!(
-                                    "whitelist add {}",
-                                    request_info.minecraft_username
-                                ))
-                                .await?;
+                        create_interaction_followup(
+                            ctx,
+                            responder,
+                            "🗳️ Vote recorded!",
+                            &format!(
+                                "Your vote has been recorded. The request now has {}/{} approvals and {}/{} denials.",
+                                approvals,
+                                server.required_approvals(),
+                                denials,
+                                server.required_denials()
+                            ),
+                            0x04a5e5,
+                            true,
+                        )
+                        .await?;
+
+                        return Ok(());
+                    }
 
-                            sqlx::query!(
-                                "
-									DELETE FROM whitelist_request
-									WHERE id = ?
-								",
-                                request_id
+                    if approved {
+                        if container_status == ContainerStateStatusEnum::RUNNING {
+                            // The request is claimed before the player is whitelisted, so a
+                            // decision made at the same time can't undo it
+                            let claimed = requests::set_status(
+                                &data.db,
+                                &request_id,
+                                WhitelistRequest::PENDING,
+                                WhitelistRequest::APPROVED,
+                                Some(&reviewer_id),
+                                None,
                             )
-                            .execute(&data.db)
                             .await?;
+                            if !claimed {
+                                create_error_followup(
+                                    ctx,
+                                    responder,
+                                    "Already decided!",
+                                    "Another reviewer decided this request first",
+                                )
+                                .await?;
 
-                            if config.whitelist.send_approval_dm {
-                                if let Err(error) = user
-									.dm(
-										ctx.http(),
-										CreateMessage::new().add_embed(
-											CreateEmbed::new()
-												.title("✅ Your whitelist request has been approved!")
-												.description(
-													format!(
-														"Your whitelist request for the server _**{}**_ has been approved!\n\n**Server ID:** `{}`\n**Minecraft Username:** `{}`",
-														server.name, server.id, request_info.minecraft_username
-													)
-												)
-												.footer(
-													create_dm_footer(guild_name, guild_icon)
-												)
-												.color(0x40a02b),
-										),
-									)
-									.await
-								{
-									error!("Error sending DM: {:?}", error);
-								};
+                                return Ok(());
+                            }
+                            if let Err(err) = requests::whitelist_player(
+                                &config,
+                                server,
+                                &request_info.minecraft_username,
+                            )
+                            .await
+                            {
+                                requests::reopen(&data.db, &request_id).await?;
+                                return Err(err);
+                            }
+                            requests::record_vote(
+                                &data.db,
+                                &request_id,
+                                &reviewer_id,
+                                vote,
+                                duration,
+                            )
+                            .await?;
+                            request_info.expires_at = requests::start_access_timer(
+                                &data.db,
+                                &request_id,
+                                server,
+                                requests::approved_duration(&votes),
+                            )
+                            .await?;
+                            actions::run_on_approve(
+                                ctx.http(),
+                                server,
+                                Some(guild_id.get()),
+                                &request_info.discord_id,
+                                &request_info.minecraft_username,
+                            )
+                            .await;
+
+                            if whitelist_config.send_approval_dm {
+                                requests::send_approval_dm(
+                                    ctx.http(),
+                                    &request_info,
+                                    server,
+                                    create_dm_footer(guild_name, guild_icon),
+                                )
+                                .await;
                             }
 
                             message.edit(&ctx, EditMessage::new().embed(
@@ -286,11 +537,12 @@ pub async fn event_handler(
 									.title("✅ Whitelist request approved!")
 									.description(
 										format!(
-											"Whitelist request approved for <@{}>!\n\n**Request ID:** {}\n**Server ID:** {}\n**Minecraft Username:** {}",
+											"Whitelist request approved for <@{}>!\n\n**Request ID:** {}\n**Server ID:** {}\n**Minecraft Username:** {}\n**Approved by:** {}",
 											request_info.discord_id,
 											request_info.id,
 											request_info.server_id,
-											request_info.minecraft_username
+											request_info.minecraft_username,
+											format_voters(&votes, WhitelistVote::APPROVE)
 										)
 									)
 									.footer(
@@ -301,12 +553,19 @@ pub async fn event_handler(
 
                             create_interaction_followup(
                                 ctx,
-                                component_interaction,
+                                responder,
                                 ":white_check_mark: Whitelist request approved!",
-                                &format!(
-                                    "Whitelist request approved for <@{}>!",
-                                    request_info.discord_id
-                                ),
+                                &match request_info.expires_at {
+                                    Some(expires_at) => format!(
+                                        "Whitelist request approved for <@{}> until <t:{}:f>!",
+                                        request_info.discord_id,
+                                        expires_at.timestamp()
+                                    ),
+                                    None => format!(
+                                        "Whitelist request approved for <@{}>!",
+                                        request_info.discord_id
+                                    ),
+                                },
                                 0x40a02b,
                                 true,
                             )
@@ -314,24 +573,37 @@ pub async fn event_handler(
                         } else {
                             create_error_followup(
                                 ctx,
-                                component_interaction,
+                                responder,
                                 "Server not running!",
                                 "Server container is not running",
                             )
                             .await?;
                         }
-                    } else if id.contains("deny") {
-                        sqlx::query!(
-                            "
-								DELETE FROM whitelist_request
-								WHERE id = ?
-							",
-                            request_id
+                    } else {
+                        let claimed = requests::set_status(
+                            &data.db,
+                            &request_id,
+                            WhitelistRequest::PENDING,
+                            WhitelistRequest::DENIED,
+                            Some(&reviewer_id),
+                            None,
                         )
-                        .execute(&data.db)
                         .await?;
+                        if !claimed {
+                            create_error_followup(
+                                ctx,
+                                responder,
+                                "Already decided!",
+                                "Another reviewer decided this request first",
+                            )
+                            .await?;
 
-                        if config.whitelist.send_denial_dm {
+                            return Ok(());
+                        }
+                        requests::record_vote(&data.db, &request_id, &reviewer_id, vote, duration)
+                            .await?;
+
+                        if whitelist_config.send_denial_dm {
                             if let Err(error) = user
 								.dm(
 									ctx.http(),
@@ -361,11 +633,12 @@ pub async fn event_handler(
 								.title(":x: Whitelist request denied")
 								.description(
 									format!(
-										"Whitelist request denied for <@{}>.\n\n**Request ID:** {}\n**Server ID:** {}\n**Minecraft Username:** {}",
+										"Whitelist request denied for <@{}>.\n\n**Request ID:** {}\n**Server ID:** {}\n**Minecraft Username:** {}\n**Denied by:** {}",
 										request_info.discord_id,
 										request_info.id,
 										request_info.server_id,
-										request_info.minecraft_username
+										request_info.minecraft_username,
+										format_voters(&votes, WhitelistVote::DENY)
 									)
 								)
 								.footer(
@@ -376,7 +649,7 @@ pub async fn event_handler(
 
                         create_interaction_followup(
                             ctx,
-                            component_interaction,
+                            responder,
                             ":x: Whitelist request denied!",
                             &format!(
                                 "Whitelist request denied for <@{}>!",
@@ -392,9 +665,12 @@ pub async fn event_handler(
                 } else {
                     create_error_followup(
                         ctx,
-                        component_interaction,
+                        responder,
                         "Whitelist request not found!",
-                        &format!("Whitelist request `{}` not found in database", request_id),
+                        &format!(
+                            "Whitelist request `{}` not found in database or already decided",
+                            request_id
+                        ),
                     )
                     .await?;
                 }
@@ -1,19 +1,39 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use clap::Parser;
 use models::config::Config;
 use poise::{Framework, FrameworkOptions, PrefixFrameworkOptions, serenity_prelude as serenity};
 use serenity::{ClientBuilder, GatewayIntents};
 use sqlx::{MySql, Pool};
-use tracing::info;
+use tracing::{info, warn};
 
+mod access;
+mod actions;
+mod cli;
 mod commands;
 mod database;
+mod docker;
 mod error;
 mod events;
 mod models;
+mod overrides;
+mod pending;
+mod permissions;
+mod reconcile;
+mod registration;
+mod reload;
+mod requests;
+mod sync;
 mod utils;
+mod validation;
 
 struct Data {
-    config: Config,
+    config: Arc<ArcSwap<Config>>,
     db: Pool<MySql>,
+    containers: docker::ContainerResolver,
+    role_sync: Arc<sync::RoleSyncState>,
+    dev_mode: bool,
 }
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
@@ -23,16 +43,27 @@ type Context<'a> = poise::Context<'a, Data, Error>;
 async fn main() -> Result<(), error::ApplicationError> {
     tracing_subscriber::fmt::init();
 
+    let cli = cli::Cli::parse();
+    if let Some(command) = cli.command {
+        return cli::run(command).await;
+    }
+    let dev_mode = cli.dev;
+
     let discord_token = std::env::var("DISCORD_TOKEN")?;
 
     let intents = GatewayIntents::non_privileged()
         | GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::MESSAGE_CONTENT
-        | GatewayIntents::DIRECT_MESSAGES;
+        | GatewayIntents::DIRECT_MESSAGES
+        | GatewayIntents::GUILD_MEMBERS
+        | GatewayIntents::GUILD_MODERATION;
 
     let framework = Framework::builder()
         .options(FrameworkOptions {
             commands: vec![
+                commands::breeze::breeze(),
+                commands::config::config(),
+                commands::permissions::permissions(),
                 commands::servers::server(),
                 commands::servers::whitelist::whitelist(),
             ],
@@ -48,10 +79,40 @@ async fn main() -> Result<(), error::ApplicationError> {
         })
         .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
-                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                let db = database::create_pool().await?;
+                let overrides = overrides::fetch(&db).await?;
+                let config = Arc::new(ArcSwap::from_pointee(Config::load(&overrides)?));
+
+                registration::register_commands(
+                    ctx,
+                    &framework.options().commands,
+                    &config.load_full().commands,
+                    dev_mode,
+                )
+                .await?;
+
+                reload::spawn_sighup_handler(config.clone(), db.clone())?;
+                if let Err(err) = reload::spawn_file_watcher(config.clone(), db.clone()) {
+                    warn!("Config file watching is disabled: {err}");
+                }
+                requests::spawn_queue_worker(ctx.http.clone(), config.clone(), db.clone());
+                access::spawn_access_worker(ctx.http.clone(), config.clone(), db.clone());
+                pending::spawn_pending_worker(ctx.http.clone(), config.clone(), db.clone());
+                let role_sync = Arc::new(sync::RoleSyncState::default());
+                sync::spawn_role_sync(
+                    ctx.http.clone(),
+                    config.clone(),
+                    db.clone(),
+                    role_sync.clone(),
+                );
+                reconcile::spawn_reconciler(ctx.http.clone(), config.clone(), db.clone());
+
                 Ok(Data {
-                    config: Config::load()?,
-                    db: database::create_pool().await?,
+                    config,
+                    db,
+                    containers: docker::ContainerResolver::default(),
+                    role_sync,
+                    dev_mode,
                 })
             })
         })
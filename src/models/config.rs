@@ -1,47 +1,991 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_default_utils::{default_bool, serde_inline_default};
+use tracing::warn;
+
+use crate::{
+    error::ApplicationError,
+    models::database::ConfigOverride,
+    overrides,
+    permissions::Permission,
+    validation::{self, ConfigIssue},
+};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Config {
-    pub whitelist: WhitelistConfig,
+    /// Whitelist settings for guilds without their own `whitelist` section
+    pub whitelist: Option<WhitelistConfig>,
+    /// Per-guild settings. Once any guilds are listed, breeze only handles whitelist requests
+    /// in those guilds.
+    #[serde(default)]
+    pub guilds: Vec<GuildConfig>,
+    #[serde(default)]
+    pub commands: CommandsConfig,
+    /// Roles granted each permission on every server
+    #[serde(default)]
+    pub permissions: BTreeMap<Permission, Vec<u64>>,
     pub servers: Vec<Server>,
+    /// Networks of servers sharing one whitelist, e.g. the backends behind a Velocity proxy
+    #[serde(default)]
+    pub groups: Vec<ServerGroup>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Where slash commands are registered.
+#[derive(Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct CommandsConfig {
+    /// Register commands globally, which can take a while to propagate
+    #[serde(default = "default_bool::<true>")]
+    pub register_globally: bool,
+    /// Guilds to register commands in, where changes take effect immediately
+    #[serde(default)]
+    pub guilds: Vec<u64>,
+    /// Guild to register commands in when running in dev mode, instead of anywhere else
+    pub dev_guild: Option<u64>,
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self {
+            register_globally: true,
+            guilds: vec![],
+            dev_guild: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct GuildConfig {
+    pub id: u64,
+    /// Whitelist settings for this guild, defaults to the top-level `whitelist` section
+    pub whitelist: Option<WhitelistConfig>,
+    /// IDs of the servers visible in this guild, defaults to all servers
+    pub servers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
 pub struct WhitelistConfig {
+    /// Whether members with the Administrator permission can review requests
     #[serde(default = "default_bool::<true>")]
     pub allow_admin: bool,
+    /// Roles that can approve or deny whitelist requests
     pub allowed_roles: Vec<u64>,
+    /// Roles pinged when a new whitelist request is posted
     pub ping_roles: Vec<u64>,
+    /// Channel whitelist requests are posted to
     pub request_channel: u64,
     #[serde(default = "default_bool::<true>")]
     pub send_approval_dm: bool,
     #[serde(default = "default_bool::<false>")]
     pub send_denial_dm: bool,
+    /// Skip checking requested usernames on servers that don't set their own
+    /// `username_validation`
     #[serde(default = "default_bool::<false>")]
     pub skip_username_validation: bool,
+    /// Rules under which requests are approved without waiting for reviewers. A request is
+    /// approved if it matches every condition of any rule.
+    #[serde(default)]
+    pub auto_approve: Vec<AutoApproveRule>,
+    /// Requirements members have to meet before they can request whitelisting
+    #[serde(default)]
+    pub eligibility: EligibilityConfig,
+    /// Remind reviewers of requests that have been pending for a while
+    pub reminders: Option<ReminderConfig>,
+    /// Remove members' accounts from the whitelists when they leave or are banned
+    #[serde(default = "default_bool::<false>")]
+    pub revoke_on_leave: bool,
+    /// Channel revocations are logged to, defaults to the request channel of each server
+    pub log_channel: Option<u64>,
 }
 
+/// Digests of requests that have been waiting for reviewers for a while, pinging the roles that
+/// are pinged for new requests.
 #[serde_inline_default]
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct ReminderConfig {
+    /// How long a request has to be pending before it's included, in hours
+    pub after_hours: u32,
+    /// Time between digests, in hours
+    #[serde_inline_default(24)]
+    pub interval_hours: u32,
+    /// Channel digests are sent to, defaults to the request channel of each request's server
+    pub channel: Option<u64>,
+}
+
+/// Gates checked before a whitelist request is accepted. Gates that aren't set are skipped.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct EligibilityConfig {
+    /// Minimum age of the requester's Discord account, in days
+    pub min_account_age_days: Option<u32>,
+    /// Minimum time since the requester joined the guild, in days
+    pub min_membership_days: Option<u32>,
+    /// The requester needs at least one of these roles
+    pub required_roles: Option<Vec<u64>>,
+    /// Maximum number of pending requests per member
+    pub max_pending_requests: Option<u32>,
+    /// Maximum number of distinct Minecraft accounts a member can request
+    pub max_accounts_per_user: Option<u32>,
+    /// Time a member has to wait after a denial before requesting the same server again, in hours
+    pub denial_cooldown_hours: Option<u32>,
+    /// Minecraft usernames or UUIDs that can't be requested
+    #[serde(default)]
+    pub blocked_players: Vec<String>,
+}
+
+/// Conditions under which a whitelist request is approved automatically. Conditions that aren't
+/// set are ignored, but at least one has to be.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct AutoApproveRule {
+    /// The requester has at least one of these roles
+    pub roles: Option<Vec<u64>>,
+    /// The requester joined the guild at least this many days ago
+    pub min_membership_days: Option<u32>,
+    /// A reviewer approved a request from the requester for the same Minecraft account before
+    #[serde(default)]
+    pub linked_account: bool,
+    /// The requester's Minecraft account is already whitelisted on another server in the same
+    /// `group`
+    #[serde(default)]
+    pub whitelisted_in_group: bool,
+}
+
+impl AutoApproveRule {
+    pub fn has_conditions(&self) -> bool {
+        self.roles.is_some()
+            || self.min_membership_days.is_some()
+            || self.linked_account
+            || self.whitelisted_in_group
+    }
+}
+
+#[serde_inline_default]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
 pub struct Server {
     // maybe name shouldn't be required and just default to the id?
     pub name: String,
     pub id: String,
-    pub container_id: String,
+    /// ID of the server's Docker container
+    pub container_id: Option<String>,
+    /// Name of the server's Docker container, used if `container_id` isn't set
+    pub container_name: Option<String>,
+    /// Compose project of the server's container, used together with `compose_service`
+    pub compose_project: Option<String>,
+    /// Compose service of the server's container, used together with `compose_project`
+    pub compose_service: Option<String>,
     #[serde_inline_default("localhost".to_string())]
     pub address: String,
+    /// Address players connect to, shown in welcome messages. Defaults to `address`.
+    pub public_address: Option<String>,
     pub query_port: u16,
     pub rcon_port: u16,
-    #[serde(default = "String::new")]
-    pub rcon_password: String,
+    /// Supports `${ENV_VAR}` interpolation like every other string value
+    #[serde(default)]
+    pub rcon_password: Secret,
+    /// File to read the RCON password from, e.g. a Docker secret
+    pub rcon_password_file: Option<PathBuf>,
+    /// Group of servers this server belongs to, used by the `whitelisted_in_group` auto-approve
+    /// condition and to share the whitelist of a group listed in `groups`
+    pub group: Option<String>,
+    /// Roles granted each permission on this server only
+    #[serde(default)]
+    pub permissions: BTreeMap<Permission, Vec<u64>>,
+    /// Whitelist settings for requests to this server, overriding the guild's `whitelist` section
+    pub whitelist: Option<ServerWhitelistConfig>,
+}
+
+/// A network of servers whose members are given the `group` ID. Requests can target the group
+/// by its ID, and approving them whitelists the player on the whole network.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct ServerGroup {
+    pub id: String,
+    pub name: String,
+    /// ID of the server players join the network through. Requests to the group are recorded
+    /// for it, or for the first member if there's no proxy.
+    pub proxy: Option<String>,
+    /// Which servers approved players are whitelisted on
+    #[serde(default)]
+    pub whitelist_on: GroupWhitelistTarget,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupWhitelistTarget {
+    /// Every member server except the proxy
+    #[default]
+    All,
+    /// Only the proxy, for proxies that enforce the whitelist themselves
+    Proxy,
+}
+
+/// Whitelist settings a server can override, so each server's moderators only handle requests
+/// for their own server.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct ServerWhitelistConfig {
+    /// Channel requests for this server are posted to
+    pub request_channel: Option<u64>,
+    /// Roles pinged when a request for this server is posted
+    pub ping_roles: Option<Vec<u64>>,
+    /// Roles that can approve or deny requests for this server, replacing `allowed_roles`
+    pub approver_roles: Option<Vec<u64>>,
+    /// Number of approvals needed before a player is whitelisted, defaults to 1
+    pub required_approvals: Option<u32>,
+    /// Number of denials that reject a request, defaults to 1
+    pub required_denials: Option<u32>,
+    /// Auto-approve rules for this server, replacing the guild's rules
+    pub auto_approve: Option<Vec<AutoApproveRule>>,
+    /// Time after which pending requests for this server expire, in hours
+    pub request_ttl_hours: Option<u32>,
+    /// Approvals on this server only grant access for this long, in hours
+    pub access_hours: Option<u32>,
+    /// Kick players who are online when their temporary access runs out
+    #[serde(default)]
+    pub kick_on_expiry: bool,
+    /// Role that grants access to this server. Members with a linked Minecraft account are
+    /// whitelisted when they get it and removed when they lose it.
+    pub whitelist_role: Option<u64>,
+    /// Only report what syncing `whitelist_role` would change instead of changing it
+    #[serde(default)]
+    pub role_sync_dry_run: bool,
+    /// What happens in Discord when a request for this server is approved, undone when the
+    /// player is removed again
+    pub on_approve: Option<OnApproveConfig>,
+    /// Lets Bedrock players join through Geyser and Floodgate
+    pub bedrock: Option<BedrockConfig>,
+    /// How requested usernames are checked. Defaults to `mojang`, or to `none` with
+    /// `skip_username_validation`.
+    pub username_validation: Option<UsernameValidation>,
+    /// Pattern usernames have to match with the `regex` strategy, defaults to vanilla's rules
+    pub username_pattern: Option<String>,
+    /// Command whitelisting a Java player, defaults to `whitelist add {username}`. Can also use
+    /// `{uuid}`.
+    pub whitelist_add: Option<String>,
+    /// Command removing a Java player from the whitelist, defaults to `whitelist remove
+    /// {username}`. Can also use `{uuid}`.
+    pub whitelist_remove: Option<String>,
+    /// Command kicking a player whose temporary access ran out, defaults to `kick {username}
+    /// {message}`
+    pub kick: Option<String>,
+    /// Questions requesters have to answer, at most 20, asked in pages of up to five
+    #[serde(default)]
+    pub application_form: Vec<FormQuestion>,
+}
+
+/// How requested usernames are checked. Bedrock gamertags are only looked up with `mojang`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UsernameValidation {
+    /// The username has to belong to a Minecraft account, and gamertags to an Xbox account
+    Mojang,
+    /// The username only has to match `username_pattern`
+    Regex,
+    /// The username has to be valid on an offline-mode server, where `{uuid}` is the offline
+    /// UUID derived from it
+    Offline,
+    /// Any username is accepted
+    None,
+}
+
+/// How Bedrock players are whitelisted on a server running Floodgate. Commands can use
+/// `{gamertag}` and `{username}`, the gamertag with the Floodgate prefix.
+#[serde_inline_default]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct BedrockConfig {
+    /// Floodgate's `username-prefix`, which Bedrock players are told apart from Java players by
+    #[serde_inline_default(".".to_string())]
+    pub prefix: String,
+    /// Endpoint looking up the XUID of a gamertag, with `{gamertag}` in place of the gamertag
+    #[serde_inline_default("https://api.geysermc.org/v2/xbox/xuid/{gamertag}".to_string())]
+    pub xuid_lookup_url: String,
+    /// Command whitelisting a Bedrock player
+    #[serde_inline_default("fwhitelist add {gamertag}".to_string())]
+    pub whitelist_add: String,
+    /// Command removing a Bedrock player from the whitelist
+    #[serde_inline_default("fwhitelist remove {gamertag}".to_string())]
+    pub whitelist_remove: String,
+}
+
+/// Actions run when a request is approved. Templates can use `{minecraft_username}`,
+/// `{discord_name}`, `{server_name}`, `{server_id}`, `{address}` and `{mention}`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct OnApproveConfig {
+    /// Roles given to the requester
+    #[serde(default)]
+    pub roles: Vec<u64>,
+    /// Nickname given to the requester, e.g. `{discord_name} ({minecraft_username})`
+    pub nickname: Option<String>,
+    /// Channel a welcome message is posted in
+    pub welcome_channel: Option<u64>,
+    /// Welcome message posted in `welcome_channel`
+    pub welcome_message: Option<String>,
+}
+
+impl OnApproveConfig {
+    pub const DEFAULT_WELCOME_MESSAGE: &str = "Welcome {mention}! You've been whitelisted on **{server_name}** as `{minecraft_username}`, join at `{address}`.";
+}
+
+/// A question of a server's application form, asked in a Discord modal.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct FormQuestion {
+    /// Shown above the text input, at most 45 characters
+    pub label: String,
+    /// Shown inside the empty text input, at most 100 characters
+    pub placeholder: Option<String>,
+    #[serde(default = "default_bool::<true>")]
+    pub required: bool,
+    /// Use a multi-line text input
+    #[serde(default)]
+    pub long: bool,
+    pub min_length: Option<u16>,
+    /// At most 4000 characters
+    pub max_length: Option<u16>,
+}
+
+impl FormQuestion {
+    pub const MAX_LABEL_LENGTH: usize = 45;
+    pub const MAX_PLACEHOLDER_LENGTH: usize = 100;
+    pub const MAX_ANSWER_LENGTH: u16 = 4000;
+    /// Discord allows at most five text inputs per modal
+    pub const PER_PAGE: usize = 5;
+    pub const MAX_QUESTIONS: usize = 20;
+}
+
+/// A config value that must never end up in logs or error messages.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(\"***\")")
+    }
+}
+
+/// How a server's Docker container is looked up.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ContainerRef {
+    Id(String),
+    Name(String),
+    Compose { project: String, service: String },
+}
+
+impl std::fmt::Display for ContainerRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerRef::Id(id) => write!(f, "{id}"),
+            ContainerRef::Name(name) => write!(f, "{name}"),
+            ContainerRef::Compose { project, service } => write!(f, "{project}/{service}"),
+        }
+    }
+}
+
+impl Server {
+    /// Returns the channel requests for this server are posted to.
+    pub fn request_channel(&self, whitelist: &WhitelistConfig) -> u64 {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.request_channel)
+            .unwrap_or(whitelist.request_channel)
+    }
+
+    /// Returns the roles pinged when a request for this server is posted.
+    pub fn ping_roles<'a>(&'a self, whitelist: &'a WhitelistConfig) -> &'a [u64] {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.ping_roles.as_deref())
+            .unwrap_or(&whitelist.ping_roles)
+    }
+
+    /// Returns the roles that can approve or deny requests for this server.
+    pub fn approver_roles<'a>(&'a self, whitelist: &'a WhitelistConfig) -> &'a [u64] {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.approver_roles.as_deref())
+            .unwrap_or(&whitelist.allowed_roles)
+    }
+
+    /// Returns the rules under which requests for this server are approved automatically.
+    pub fn auto_approve_rules<'a>(
+        &'a self,
+        whitelist: &'a WhitelistConfig,
+    ) -> &'a [AutoApproveRule] {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.auto_approve.as_deref())
+            .unwrap_or(&whitelist.auto_approve)
+    }
+
+    /// Returns the questions requesters have to answer for this server.
+    pub fn application_form(&self) -> &[FormQuestion] {
+        self.whitelist
+            .as_ref()
+            .map(|w| w.application_form.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns the time after which pending requests for this server expire, in hours.
+    pub fn request_ttl_hours(&self) -> Option<u32> {
+        self.whitelist.as_ref().and_then(|w| w.request_ttl_hours)
+    }
+
+    /// Returns how long approvals on this server grant access for, in hours.
+    pub fn access_hours(&self) -> Option<u32> {
+        self.whitelist.as_ref().and_then(|w| w.access_hours)
+    }
+
+    /// Returns whether players are kicked when their temporary access runs out.
+    pub fn kick_on_expiry(&self) -> bool {
+        self.whitelist.as_ref().is_some_and(|w| w.kick_on_expiry)
+    }
+
+    /// Returns the role that grants access to this server, if access follows a role.
+    pub fn whitelist_role(&self) -> Option<u64> {
+        self.whitelist.as_ref().and_then(|w| w.whitelist_role)
+    }
+
+    /// Returns whether syncing the whitelist role only reports what it would change.
+    pub fn role_sync_dry_run(&self) -> bool {
+        self.whitelist.as_ref().is_some_and(|w| w.role_sync_dry_run)
+    }
+
+    /// Usernames vanilla servers accept.
+    pub const DEFAULT_USERNAME_PATTERN: &str = "^[A-Za-z0-9_]{3,16}$";
+
+    /// Returns how requested Java usernames are checked. The server's own setting wins over the
+    /// whitelist's `skip_username_validation`.
+    pub fn username_validation(&self, whitelist: &WhitelistConfig) -> UsernameValidation {
+        match self.whitelist.as_ref().and_then(|w| w.username_validation) {
+            Some(validation) => validation,
+            None if whitelist.skip_username_validation => UsernameValidation::None,
+            None => UsernameValidation::Mojang,
+        }
+    }
+
+    /// Whether the server runs in offline mode, where player UUIDs are derived from usernames.
+    pub fn is_offline(&self) -> bool {
+        self.whitelist.as_ref().and_then(|w| w.username_validation)
+            == Some(UsernameValidation::Offline)
+    }
+
+    /// Returns the pattern usernames have to match with the `regex` strategy.
+    pub fn username_pattern(&self) -> Result<Regex, regex::Error> {
+        let pattern = self
+            .whitelist
+            .as_ref()
+            .and_then(|w| w.username_pattern.as_deref());
+        Regex::new(pattern.unwrap_or(Self::DEFAULT_USERNAME_PATTERN))
+    }
+
+    /// Returns the command whitelisting a Java player.
+    pub fn whitelist_add_command(&self) -> &str {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.whitelist_add.as_deref())
+            .unwrap_or("whitelist add {username}")
+    }
+
+    /// Returns the command removing a Java player from the whitelist.
+    pub fn whitelist_remove_command(&self) -> &str {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.whitelist_remove.as_deref())
+            .unwrap_or("whitelist remove {username}")
+    }
+
+    /// Returns the command kicking a player with a message.
+    pub fn kick_command(&self) -> &str {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.kick.as_deref())
+            .unwrap_or("kick {username} {message}")
+    }
+
+    /// Returns how Bedrock players are whitelisted, if the server accepts them.
+    pub fn bedrock(&self) -> Option<&BedrockConfig> {
+        self.whitelist.as_ref().and_then(|w| w.bedrock.as_ref())
+    }
+
+    /// Returns what happens in Discord when a request for this server is approved.
+    pub fn on_approve(&self) -> Option<&OnApproveConfig> {
+        self.whitelist.as_ref().and_then(|w| w.on_approve.as_ref())
+    }
+
+    /// Returns the address players connect to.
+    pub fn public_address(&self) -> &str {
+        self.public_address.as_deref().unwrap_or(&self.address)
+    }
+
+    /// Returns the number of approvals needed before a player is whitelisted on this server.
+    pub fn required_approvals(&self) -> usize {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.required_approvals)
+            .unwrap_or(1) as usize
+    }
+
+    /// Returns the number of denials that reject a request for this server.
+    pub fn required_denials(&self) -> usize {
+        self.whitelist
+            .as_ref()
+            .and_then(|w| w.required_denials)
+            .unwrap_or(1) as usize
+    }
+
+    /// Returns how this server's container should be looked up, preferring a raw container ID,
+    /// then a container name, then a compose project + service pair.
+    pub fn container_ref(&self) -> Option<ContainerRef> {
+        let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.is_empty());
+
+        if let Some(id) = non_empty(&self.container_id) {
+            return Some(ContainerRef::Id(id));
+        }
+        if let Some(name) = non_empty(&self.container_name) {
+            return Some(ContainerRef::Name(name));
+        }
+        match (
+            non_empty(&self.compose_project),
+            non_empty(&self.compose_service),
+        ) {
+            (Some(project), Some(service)) => Some(ContainerRef::Compose { project, service }),
+            _ => None,
+        }
+    }
 }
 
 impl Config {
-    pub fn load() -> Result<Self, crate::error::ApplicationError> {
-        let config_path = std::env::var("CONFIG_PATH").unwrap_or("config.toml".to_string());
-        let config_str = std::fs::read_to_string(config_path)?;
-        let config: Config = toml::from_str(&config_str)?;
+    /// Returns the settings of the given guild, if it has any.
+    pub fn guild(&self, guild_id: u64) -> Option<&GuildConfig> {
+        self.guilds.iter().find(|g| g.id == guild_id)
+    }
+
+    /// Returns the whitelist settings used in the given guild, or `None` if whitelist requests
+    /// aren't set up there.
+    pub fn whitelist_for(&self, guild_id: Option<u64>) -> Option<&WhitelistConfig> {
+        if self.guilds.is_empty() {
+            return self.whitelist.as_ref();
+        }
+
+        let guild = self.guild(guild_id?)?;
+        guild.whitelist.as_ref().or(self.whitelist.as_ref())
+    }
+
+    /// Returns the servers visible in the given guild. Outside of guilds all servers are visible.
+    pub fn servers_for(&self, guild_id: Option<u64>) -> Vec<&Server> {
+        let visible_ids = match guild_id {
+            Some(guild_id) if !self.guilds.is_empty() => match self.guild(guild_id) {
+                Some(guild) => guild.servers.as_ref(),
+                None => return vec![],
+            },
+            _ => None,
+        };
+
+        self.servers
+            .iter()
+            .filter(|s| visible_ids.is_none_or(|ids| ids.contains(&s.id)))
+            .collect()
+    }
+
+    /// Returns the group with the given ID.
+    pub fn group(&self, group_id: &str) -> Option<&ServerGroup> {
+        self.groups.iter().find(|g| g.id == group_id)
+    }
+
+    /// Returns the servers in the given group.
+    pub fn group_members(&self, group_id: &str) -> Vec<&Server> {
+        self.servers
+            .iter()
+            .filter(|s| s.group.as_deref() == Some(group_id))
+            .collect()
+    }
+
+    /// Returns the server requests to the group are recorded for: its proxy, or its first member
+    /// if it doesn't have one.
+    pub fn group_entry(&self, group: &ServerGroup) -> Option<&Server> {
+        match &group.proxy {
+            Some(proxy) => self.servers.iter().find(|s| &s.id == proxy),
+            None => self.group_members(&group.id).into_iter().next(),
+        }
+    }
+
+    /// Returns the server a request with the given target is recorded for, which is either a
+    /// server or a group visible in the given guild.
+    pub fn request_target(&self, guild_id: Option<u64>, target: &str) -> Option<&Server> {
+        let visible = self.servers_for(guild_id);
+        if let Some(server) = visible.iter().find(|s| s.id == target) {
+            return Some(server);
+        }
+
+        let entry = self.group_entry(self.group(target)?)?;
+        visible.into_iter().find(|s| s.id == entry.id)
+    }
+
+    /// Returns the servers a player approved for the server is whitelisted on. That's every
+    /// backend of its group, or only the group's proxy, and otherwise just the server itself.
+    pub fn whitelist_targets<'a>(&'a self, server: &'a Server) -> Vec<&'a Server> {
+        let Some(group) = server.group.as_deref().and_then(|id| self.group(id)) else {
+            return vec![server];
+        };
+
+        let targets: Vec<&Server> = match group.whitelist_on {
+            GroupWhitelistTarget::All => self
+                .group_members(&group.id)
+                .into_iter()
+                .filter(|s| group.proxy.as_ref() != Some(&s.id))
+                .collect(),
+            GroupWhitelistTarget::Proxy => self.group_entry(group).into_iter().collect(),
+        };
+        match targets.is_empty() {
+            true => vec![server],
+            false => targets,
+        }
+    }
+
+    /// Path of the config file, taken from `CONFIG_PATH` and defaulting to `config.toml`.
+    pub fn path() -> PathBuf {
+        std::env::var("CONFIG_PATH")
+            .unwrap_or("config.toml".to_string())
+            .into()
+    }
+
+    /// Generates the JSON Schema of the config file.
+    pub fn json_schema() -> String {
+        let schema = schemars::schema_for!(Config);
+        let mut json = serde_json::to_string_pretty(&schema).expect("schema is valid JSON");
+        json.push('\n');
+        json
+    }
+
+    /// Loads the config file with the given overrides applied, failing if it contains any
+    /// errors. Warnings are logged.
+    pub fn load(overrides: &[ConfigOverride]) -> Result<Self, ApplicationError> {
+        let (config, issues) = Self::load_with_issues(overrides)?;
+
+        let (errors, warnings): (Vec<ConfigIssue>, Vec<ConfigIssue>) =
+            issues.into_iter().partition(|issue| issue.is_error());
+
+        for warning in warnings {
+            warn!("Config warning: {warning}");
+        }
+
+        if !errors.is_empty() {
+            return Err(ApplicationError::InvalidConfig(
+                errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ));
+        }
+
         Ok(config)
     }
+
+    /// Loads the config file with the given overrides applied and returns it along with every
+    /// problem found in it, including unknown keys. Only fails if the file can't be read or
+    /// deserialized.
+    pub fn load_with_issues(
+        overrides: &[ConfigOverride],
+    ) -> Result<(Self, Vec<ConfigIssue>), ApplicationError> {
+        let config_str = std::fs::read_to_string(Self::path())?;
+
+        // Errors are reduced to their message and location, since the offending line may
+        // contain secrets
+        let mut table: toml::Value = toml::from_str(&config_str).map_err(|err| {
+            let line = err
+                .span()
+                .map(|span| config_str[..span.start].lines().count().max(1));
+            let message = err.message().trim_end().to_string();
+            ApplicationError::InvalidConfig(match line {
+                Some(line) => format!("line {line}: {message}"),
+                None => message,
+            })
+        })?;
+
+        // Only the file is interpolated, overrides are taken literally so they can't be used to
+        // read the environment
+        let mut issues = vec![];
+        interpolate_env(&mut table, "", &mut issues);
+        overrides::apply(&mut table, overrides, &mut issues);
+
+        let mut unknown_keys = vec![];
+        let mut on_unknown_key = |path: serde_ignored::Path| unknown_keys.push(path.to_string());
+        let mut config: Config = serde_path_to_error::deserialize(
+            serde_ignored::Deserializer::new(table, &mut on_unknown_key),
+        )
+        .map_err(|err| {
+            let path = err.path().to_string();
+            let message = err.into_inner().message().trim_end().to_string();
+            ApplicationError::InvalidConfig(match path.as_str() {
+                "." => message,
+                _ => format!("{path}: {message}"),
+            })
+        })?;
+
+        issues.extend(
+            unknown_keys
+                .into_iter()
+                .map(|key| ConfigIssue::warning(key, "unknown key, is it misspelled?")),
+        );
+        issues.extend(config.read_secret_files());
+        issues.extend(validation::validate(&config));
+
+        Ok((config, issues))
+    }
+
+    /// Reads secrets that are stored in separate files into the config.
+    fn read_secret_files(&mut self) -> Vec<ConfigIssue> {
+        let mut issues = vec![];
+
+        for (i, server) in self.servers.iter_mut().enumerate() {
+            let Some(password_file) = &server.rcon_password_file else {
+                continue;
+            };
+            let path = format!("servers[{i}].rcon_password_file");
+
+            if !server.rcon_password.is_empty() {
+                issues.push(ConfigIssue::error(
+                    path.clone(),
+                    "can't be used together with `rcon_password`",
+                ));
+            }
+
+            match std::fs::read_to_string(password_file) {
+                Ok(password) => {
+                    server.rcon_password = Secret::new(password.trim_end_matches(['\r', '\n']))
+                }
+                Err(err) => issues.push(ConfigIssue::error(
+                    path,
+                    format!("failed to read `{}`: {err}", password_file.display()),
+                )),
+            }
+        }
+
+        issues
+    }
+}
+
+/// Replaces `${ENV_VAR}` references in all string values with the value of the environment
+/// variable. `$${` is kept as a literal `${`.
+fn interpolate_env(value: &mut toml::Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    match value {
+        toml::Value::String(s) => match interpolate_str(s, |name| std::env::var(name).ok()) {
+            Ok(interpolated) => *s = interpolated,
+            Err(var) => issues.push(ConfigIssue::error(
+                path,
+                format!("environment variable `{var}` is not set"),
+            )),
+        },
+        toml::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                interpolate_env(value, &format!("{path}[{i}]"), issues);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{path}.{key}"),
+                };
+                interpolate_env(value, &path, issues);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Interpolates a single string with the variables returned by `lookup`, returning the name of
+/// the first unset variable on failure.
+fn interpolate_str(s: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut interpolated = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        if let Some(after_escape) = after.strip_prefix("${") {
+            interpolated.push_str("${");
+            rest = after_escape;
+        } else if let Some((name, after_var)) =
+            after.strip_prefix('{').and_then(|var| var.split_once('}'))
+        {
+            let value = lookup(name).ok_or_else(|| name.to_string())?;
+            interpolated.push_str(&value);
+            rest = after_var;
+        } else {
+            interpolated.push('$');
+            rest = after;
+        }
+    }
+    interpolated.push_str(rest);
+
+    Ok(interpolated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Config, interpolate_str};
+
+    /// Interpolates with a fixed set of variables instead of the environment.
+    fn interpolate(s: &str) -> Result<String, String> {
+        let variables = HashMap::from([("BREEZE_TEST_INTERPOLATED", "secret")]);
+        interpolate_str(s, |name| variables.get(name).map(|v| v.to_string()))
+    }
+
+    #[test]
+    fn json_schema_is_up_to_date() {
+        assert!(
+            Config::json_schema() == include_str!("../../config.schema.json"),
+            "config.schema.json is out of date, regenerate it with `cargo run -- config-schema > config.schema.json`"
+        );
+    }
+
+    #[test]
+    fn interpolates_set_variables() {
+        assert_eq!(
+            interpolate("pass-${BREEZE_TEST_INTERPOLATED}!"),
+            Ok("pass-secret!".to_string())
+        );
+    }
+
+    #[test]
+    fn fails_on_unset_variables() {
+        assert_eq!(
+            interpolate("${BREEZE_TEST_UNSET}"),
+            Err("BREEZE_TEST_UNSET".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_escaped_references() {
+        assert_eq!(
+            interpolate("$${BREEZE_TEST_UNSET} and $$${BREEZE_TEST_UNSET}"),
+            Ok("${BREEZE_TEST_UNSET} and $${BREEZE_TEST_UNSET}".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_lone_dollar_signs() {
+        assert_eq!(interpolate("$5 or $"), Ok("$5 or $".to_string()));
+    }
+
+    #[test]
+    fn keeps_unterminated_references() {
+        assert_eq!(
+            interpolate("${BREEZE_TEST_UNSET"),
+            Ok("${BREEZE_TEST_UNSET".to_string())
+        );
+    }
+
+    #[test]
+    fn fails_on_empty_names() {
+        assert_eq!(interpolate("${}"), Err(String::new()));
+    }
+
+    fn network_config(whitelist_on: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            [[servers]]
+            name = "Lobby"
+            id = "lobby"
+            query_port = 25565
+            rcon_port = 25575
+
+            [[servers]]
+            name = "Proxy"
+            id = "proxy"
+            query_port = 25566
+            rcon_port = 25576
+            group = "network"
+
+            [[servers]]
+            name = "Survival"
+            id = "survival"
+            query_port = 25567
+            rcon_port = 25577
+            group = "network"
+
+            [[servers]]
+            name = "Creative"
+            id = "creative"
+            query_port = 25568
+            rcon_port = 25578
+            group = "network"
+
+            [[servers]]
+            name = "Event"
+            id = "event"
+            query_port = 25569
+            rcon_port = 25579
+            group = "undefined"
+
+            [[groups]]
+            id = "network"
+            name = "Network"
+            proxy = "proxy"
+            whitelist_on = "{whitelist_on}"
+            "#
+        ))
+        .unwrap()
+    }
+
+    fn target_ids(config: &Config, server_id: &str) -> Vec<String> {
+        let server = config.servers.iter().find(|s| s.id == server_id).unwrap();
+        config
+            .whitelist_targets(server)
+            .into_iter()
+            .map(|s| s.id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn whitelists_servers_outside_groups_on_themselves() {
+        let config = network_config("all");
+
+        assert_eq!(target_ids(&config, "lobby"), ["lobby"]);
+        assert_eq!(target_ids(&config, "event"), ["event"]);
+    }
+
+    #[test]
+    fn whitelists_groups_on_their_backends() {
+        let config = network_config("all");
+
+        assert_eq!(target_ids(&config, "proxy"), ["survival", "creative"]);
+        assert_eq!(target_ids(&config, "survival"), ["survival", "creative"]);
+    }
+
+    #[test]
+    fn whitelists_groups_on_their_proxy() {
+        let config = network_config("proxy");
+
+        assert_eq!(target_ids(&config, "proxy"), ["proxy"]);
+        assert_eq!(target_ids(&config, "creative"), ["proxy"]);
+    }
+
+    #[test]
+    fn whitelists_groups_without_backends_on_the_server() {
+        let mut config = network_config("all");
+        config
+            .servers
+            .retain(|s| s.id != "survival" && s.id != "creative");
+
+        assert_eq!(target_ids(&config, "proxy"), ["proxy"]);
+    }
 }
@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::overrides;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WhitelistRequest {
     pub id: String,
@@ -8,4 +10,118 @@ pub struct WhitelistRequest {
     pub discord_id: String,
     pub minecraft_username: String,
     pub created_at: Option<DateTime<Utc>>,
+    pub guild_id: Option<String>,
+    /// One of the `WhitelistRequest::*` status constants
+    pub status: String,
+    /// Reviewer who made the final decision, `None` for automatic decisions
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    /// Why the request was decided the way it was, e.g. the auto-approve rule it matched
+    pub reason: Option<String>,
+    /// Channel the staff message was posted in
+    pub channel_id: Option<String>,
+    pub message_id: Option<String>,
+    /// When temporary access granted by the request runs out
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the player was told their temporary access is about to run out
+    pub expiry_warned_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl WhitelistRequest {
+    /// Waiting for reviewers.
+    pub const PENDING: &str = "pending";
+    /// The player was whitelisted.
+    pub const APPROVED: &str = "approved";
+    /// Approved, but the player is only whitelisted once the server is running.
+    pub const QUEUED: &str = "queued";
+    pub const DENIED: &str = "denied";
+    /// Withdrawn by the requester before it was decided.
+    pub const CANCELLED: &str = "cancelled";
+    /// Not decided within the server's `request_ttl_hours`.
+    pub const EXPIRED: &str = "expired";
+    /// Approved, but the player was removed from the whitelist again.
+    pub const REVOKED: &str = "revoked";
+    /// Being removed from the whitelist, retried until the server can be reached.
+    pub const REVOKING: &str = "revoking";
+
+    /// Returns a link to the staff message of this request, falling back to its ID for requests
+    /// whose message wasn't recorded.
+    pub fn link(&self) -> String {
+        match (&self.guild_id, &self.channel_id, &self.message_id) {
+            (Some(guild_id), Some(channel_id), Some(message_id)) => format!(
+                "[`{}`](https://discord.com/channels/{guild_id}/{channel_id}/{message_id})",
+                self.id
+            ),
+            _ => format!("`{}`", self.id),
+        }
+    }
+}
+
+/// A reviewer's vote on a whitelist request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WhitelistVote {
+    pub request_id: String,
+    pub reviewer_id: String,
+    /// Either [WhitelistVote::APPROVE] or [WhitelistVote::DENY]
+    pub vote: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// How long the reviewer approved access for, if they chose a duration
+    pub duration_minutes: Option<i64>,
+}
+
+impl WhitelistVote {
+    pub const APPROVE: &str = "approve";
+    pub const DENY: &str = "deny";
+}
+
+/// An answer to a question of a server's application form.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WhitelistAnswer {
+    pub request_id: String,
+    /// Position of the question in the form
+    pub position: i32,
+    /// The question's label at the time it was answered
+    pub question: String,
+    pub answer: String,
+}
+
+/// A config value set at runtime, overriding the value from the config file.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ConfigOverride {
+    /// `global`, `guild:<guild id>` or `server:<server id>`
+    pub scope: String,
+    /// Dotted path of the setting within the scope, e.g. `whitelist.ping_roles`
+    pub setting: String,
+    /// TOML encoded value
+    pub value: String,
+    pub updated_by: String,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Debug for ConfigOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = match overrides::is_secret(&self.setting) {
+            true => overrides::REDACTED,
+            false => &self.value,
+        };
+        f.debug_struct("ConfigOverride")
+            .field("scope", &self.scope)
+            .field("setting", &self.setting)
+            .field("value", &value)
+            .field("updated_by", &self.updated_by)
+            .field("updated_at", &self.updated_at)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigAuditEntry {
+    pub id: u64,
+    pub scope: String,
+    pub setting: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_by: String,
+    pub changed_at: Option<DateTime<Utc>>,
 }
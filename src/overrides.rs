@@ -0,0 +1,505 @@
+use std::{collections::HashSet, sync::Arc};
+
+use arc_swap::ArcSwap;
+use sqlx::{MySql, Pool};
+
+use crate::{
+    error::ApplicationError,
+    models::{
+        config::Config,
+        database::{ConfigAuditEntry, ConfigOverride},
+    },
+    reload::{CONFIG_LOCK, ConfigDiff},
+    validation::ConfigIssue,
+};
+
+/// Scope of settings at the top level of the config file.
+pub const GLOBAL_SCOPE: &str = "global";
+
+/// Setting that hides a server defined in the config file when set to `true`.
+pub const REMOVED_SETTING: &str = "removed";
+
+pub fn guild_scope(guild_id: u64) -> String {
+    format!("guild:{guild_id}")
+}
+
+pub fn server_scope(server_id: &str) -> String {
+    format!("server:{server_id}")
+}
+
+/// Stored in place of secret values in the audit table.
+pub const REDACTED: &str = "***";
+
+/// Whether the setting holds a secret, whose value is never shown or kept in the audit table.
+pub fn is_secret(setting: &str) -> bool {
+    setting.contains("password")
+}
+
+/// Returns the value with secrets hidden, including those nested in tables and arrays, such as
+/// the RCON passwords of an override of `servers`.
+pub fn redact(setting: &str, value: &str) -> String {
+    if is_secret(setting) {
+        return REDACTED.to_string();
+    }
+
+    let Ok(mut parsed) = parse_value(value) else {
+        return value.to_string();
+    };
+    match redact_nested(&mut parsed) {
+        true => parsed.to_string(),
+        false => value.to_string(),
+    }
+}
+
+/// Replaces the values of secret keys, returning whether any were found.
+fn redact_nested(value: &mut toml::Value) -> bool {
+    match value {
+        toml::Value::Table(table) => {
+            table
+                .iter_mut()
+                .fold(false, |found, (key, value)| match is_secret(key) {
+                    true => {
+                        *value = REDACTED.into();
+                        true
+                    }
+                    false => redact_nested(value) || found,
+                })
+        }
+        toml::Value::Array(values) => values
+            .iter_mut()
+            .fold(false, |found, value| redact_nested(value) || found),
+        _ => false,
+    }
+}
+
+/// A change to a single override. A `value` of `None` removes the override.
+#[derive(Clone)]
+pub struct OverrideChange {
+    pub scope: String,
+    pub setting: String,
+    pub value: Option<String>,
+}
+
+impl std::fmt::Debug for OverrideChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.value.as_ref().map(|v| redact(&self.setting, v));
+        f.debug_struct("OverrideChange")
+            .field("scope", &self.scope)
+            .field("setting", &self.setting)
+            .field("value", &value)
+            .finish()
+    }
+}
+
+pub async fn fetch(db: &Pool<MySql>) -> Result<Vec<ConfigOverride>, ApplicationError> {
+    let overrides = sqlx::query_as!(
+        ConfigOverride,
+        "SELECT scope, setting, value, updated_by, updated_at FROM config_override ORDER BY scope, setting"
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(overrides)
+}
+
+pub async fn history(
+    db: &Pool<MySql>,
+    limit: u32,
+) -> Result<Vec<ConfigAuditEntry>, ApplicationError> {
+    let entries = sqlx::query_as!(
+        ConfigAuditEntry,
+        "SELECT id, scope, setting, old_value, new_value, changed_by, changed_at FROM config_audit ORDER BY id DESC LIMIT ?",
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Applies the given changes on top of the stored overrides, swapping in the resulting config.
+///
+/// The changes are only stored if the resulting config is valid and doesn't introduce unknown
+/// keys. Every change is recorded in the audit table.
+pub async fn change(
+    config: &ArcSwap<Config>,
+    db: &Pool<MySql>,
+    changes: Vec<OverrideChange>,
+    changed_by: u64,
+) -> Result<ConfigDiff, ApplicationError> {
+    let _lock = CONFIG_LOCK.lock().await;
+    let old_overrides = fetch(db).await?;
+
+    let mut overrides = old_overrides.clone();
+    for change in &changes {
+        let existing = overrides
+            .iter()
+            .position(|o| o.scope == change.scope && o.setting == change.setting);
+        match (&change.value, existing) {
+            (Some(value), Some(i)) => overrides[i].value = value.clone(),
+            (Some(value), None) => overrides.push(ConfigOverride {
+                scope: change.scope.clone(),
+                setting: change.setting.clone(),
+                value: value.clone(),
+                updated_by: changed_by.to_string(),
+                updated_at: None,
+            }),
+            (None, Some(i)) => {
+                overrides.remove(i);
+            }
+            (None, None) => {}
+        }
+    }
+
+    let old_warnings: HashSet<String> = Config::load_with_issues(&old_overrides)
+        .map(|(_, issues)| issues.iter().map(|i| i.to_string()).collect())
+        .unwrap_or_default();
+    let (new_config, issues) = Config::load_with_issues(&overrides)?;
+
+    // Reject errors as well as new warnings, which are most likely misspelled settings
+    let rejected: Vec<String> = issues
+        .iter()
+        .filter(|i| i.is_error() || !old_warnings.contains(&i.to_string()))
+        .map(|i| i.to_string())
+        .collect();
+    if !rejected.is_empty() {
+        return Err(ApplicationError::InvalidConfig(rejected.join("\n")));
+    }
+
+    let changed_by = changed_by.to_string();
+    let mut tx = db.begin().await?;
+    for change in changes {
+        let old_value = old_overrides
+            .iter()
+            .find(|o| o.scope == change.scope && o.setting == change.setting)
+            .map(|o| o.value.clone());
+        if old_value == change.value {
+            continue;
+        }
+
+        match &change.value {
+            Some(value) => {
+                sqlx::query!(
+                    "
+                    INSERT INTO config_override (scope, setting, value, updated_by)
+                    VALUES ( ?, ?, ?, ? )
+                    ON DUPLICATE KEY UPDATE value = VALUES(value), updated_by = VALUES(updated_by), updated_at = CURRENT_TIMESTAMP
+                    ",
+                    change.scope,
+                    change.setting,
+                    value,
+                    changed_by
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query!(
+                    "DELETE FROM config_override WHERE scope = ? AND setting = ?",
+                    change.scope,
+                    change.setting
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        let old_value = old_value.map(|v| redact(&change.setting, &v));
+        let new_value = change.value.map(|v| redact(&change.setting, &v));
+        sqlx::query!(
+            "
+            INSERT INTO config_audit (scope, setting, old_value, new_value, changed_by)
+            VALUES ( ?, ?, ?, ?, ? )
+            ",
+            change.scope,
+            change.setting,
+            old_value,
+            new_value,
+            changed_by
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    let diff = ConfigDiff::new(&config.load(), &new_config);
+    config.store(Arc::new(new_config));
+
+    Ok(diff)
+}
+
+/// Turns user input into a TOML value, treating anything that isn't valid TOML as a string.
+pub fn normalize_value(input: &str) -> String {
+    let input = input.trim();
+    match parse_value(input) {
+        Ok(_) => input.to_string(),
+        Err(_) => toml::Value::String(input.to_string()).to_string(),
+    }
+}
+
+fn parse_value(value: &str) -> Result<toml::Value, String> {
+    let mut table: toml::Table =
+        toml::from_str(&format!("value = {value}")).map_err(|e| e.message().to_string())?;
+
+    match (table.len(), table.remove("value")) {
+        (1, Some(value)) => Ok(value),
+        _ => Err("expected a single value".to_string()),
+    }
+}
+
+/// Rejects overrides that could expose the bot's environment or files, since only the config
+/// file itself is trusted with those.
+pub fn check_allowed(setting: &str, value: &str) -> Result<(), String> {
+    let key = setting.rsplit('.').next().unwrap_or(setting);
+    if key.ends_with("_file") {
+        return Err(format!("`{key}` can only be set in the config file"));
+    }
+    if value.contains("${") {
+        return Err("environment variables can only be used in the config file".to_string());
+    }
+
+    Ok(())
+}
+
+/// Applies the overrides to the parsed config file.
+pub fn apply(root: &mut toml::Value, overrides: &[ConfigOverride], issues: &mut Vec<ConfigIssue>) {
+    let mut removed_servers = vec![];
+
+    for o in overrides {
+        let path = format!("{}.{}", o.scope, o.setting);
+
+        if let Err(err) = check_allowed(&o.setting, &o.value) {
+            issues.push(ConfigIssue::error(path, err));
+            continue;
+        }
+
+        let value = match parse_value(&o.value) {
+            Ok(value) => value,
+            Err(err) => {
+                issues.push(ConfigIssue::error(
+                    path,
+                    format!("invalid override value: {err}"),
+                ));
+                continue;
+            }
+        };
+
+        if let Some(server_id) = o.scope.strip_prefix("server:") {
+            if o.setting == REMOVED_SETTING {
+                if value.as_bool() == Some(true) {
+                    removed_servers.push(server_id.to_string());
+                }
+                continue;
+            }
+        }
+
+        let Some(table) = scope_table(root, &o.scope) else {
+            issues.push(ConfigIssue::error(path, "unknown override scope"));
+            continue;
+        };
+
+        if let Err(err) = set_path(table, &o.setting, value) {
+            issues.push(ConfigIssue::error(path, err));
+        }
+    }
+
+    if let Some(servers) = root.get_mut("servers").and_then(|s| s.as_array_mut()) {
+        servers.retain(|server| {
+            !server
+                .get("id")
+                .and_then(|id| id.as_str())
+                .is_some_and(|id| removed_servers.iter().any(|r| r == id))
+        });
+    }
+}
+
+/// Returns the table the settings of the given scope apply to, creating guild and server
+/// entries that don't exist in the config file yet.
+fn scope_table<'a>(root: &'a mut toml::Value, scope: &str) -> Option<&'a mut toml::Table> {
+    let root = root.as_table_mut()?;
+
+    let (array_key, id_value) = match scope.split_once(':') {
+        None if scope == GLOBAL_SCOPE => return Some(root),
+        Some(("guild", id)) => ("guilds", toml::Value::Integer(id.parse().ok()?)),
+        Some(("server", id)) => ("servers", toml::Value::String(id.to_string())),
+        _ => return None,
+    };
+
+    let entries = root
+        .entry(array_key)
+        .or_insert_with(|| toml::Value::Array(vec![]))
+        .as_array_mut()?;
+
+    let index = match entries.iter().position(|e| e.get("id") == Some(&id_value)) {
+        Some(index) => index,
+        None => {
+            entries.push(toml::Value::Table(toml::Table::from_iter([(
+                "id".to_string(),
+                id_value,
+            )])));
+            entries.len() - 1
+        }
+    };
+
+    entries[index].as_table_mut()
+}
+
+fn set_path(table: &mut toml::Table, path: &str, value: toml::Value) -> Result<(), String> {
+    let (parents, key) = match path.rsplit_once('.') {
+        Some((parents, key)) => (Some(parents), key),
+        None => (None, path),
+    };
+
+    let mut table = table;
+    for part in parents.into_iter().flat_map(|p| p.split('.')) {
+        table = table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| format!("`{part}` is not a table"))?;
+    }
+    table.insert(key.to_string(), value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, redact, set_path};
+    use crate::models::database::ConfigOverride;
+
+    fn config() -> toml::Value {
+        toml::from_str(
+            r#"
+            [whitelist]
+            enabled = true
+
+            [[servers]]
+            id = "survival"
+            rcon_port = 25575
+
+            [[servers]]
+            id = "creative"
+            rcon_port = 25576
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn override_of(scope: &str, setting: &str, value: &str) -> ConfigOverride {
+        ConfigOverride {
+            scope: scope.to_string(),
+            setting: setting.to_string(),
+            value: value.to_string(),
+            updated_by: "0".to_string(),
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn set_path_creates_missing_tables() {
+        let mut table = toml::Table::new();
+        set_path(
+            &mut table,
+            "whitelist.eligibility.max_pending_requests",
+            2.into(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            table["whitelist"]["eligibility"]["max_pending_requests"].as_integer(),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn set_path_fails_on_values_in_the_way() {
+        let mut table = toml::Table::new();
+        table.insert("whitelist".to_string(), true.into());
+
+        assert!(set_path(&mut table, "whitelist.enabled", false.into()).is_err());
+    }
+
+    #[test]
+    fn applies_overrides_to_their_scopes() {
+        let mut root = config();
+        let mut issues = vec![];
+        apply(
+            &mut root,
+            &[
+                override_of("global", "whitelist.enabled", "false"),
+                override_of("server:creative", "rcon_port", "25577"),
+                override_of("guild:123", "whitelist.request_channel", "456"),
+            ],
+            &mut issues,
+        );
+
+        assert!(issues.is_empty());
+        assert_eq!(root["whitelist"]["enabled"].as_bool(), Some(false));
+        assert_eq!(root["servers"][0]["rcon_port"].as_integer(), Some(25575));
+        assert_eq!(root["servers"][1]["rcon_port"].as_integer(), Some(25577));
+        assert_eq!(root["guilds"][0]["id"].as_integer(), Some(123));
+        assert_eq!(
+            root["guilds"][0]["whitelist"]["request_channel"].as_integer(),
+            Some(456)
+        );
+    }
+
+    #[test]
+    fn removes_servers() {
+        let mut root = config();
+        let mut issues = vec![];
+        apply(
+            &mut root,
+            &[override_of("server:survival", "removed", "true")],
+            &mut issues,
+        );
+
+        assert!(issues.is_empty());
+        let servers = root["servers"].as_array().unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0]["id"].as_str(), Some("creative"));
+    }
+
+    #[test]
+    fn rejects_invalid_overrides() {
+        let mut root = config();
+        let mut issues = vec![];
+        apply(
+            &mut root,
+            &[
+                override_of("server:survival", "rcon_password_file", "\"/etc/shadow\""),
+                override_of("server:survival", "rcon_password", "\"${DISCORD_TOKEN}\""),
+                override_of("server:survival", "rcon_port", "not toml"),
+                override_of("channel:1", "enabled", "true"),
+            ],
+            &mut issues,
+        );
+
+        let paths: Vec<&str> = issues.iter().map(|issue| issue.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "server:survival.rcon_password_file",
+                "server:survival.rcon_password",
+                "server:survival.rcon_port",
+                "channel:1.enabled",
+            ]
+        );
+        assert!(issues.iter().all(|issue| issue.is_error()));
+        assert_eq!(root, config());
+    }
+
+    #[test]
+    fn redacts_nested_secrets() {
+        assert_eq!(redact("rcon_password", "\"hunter2\""), "***");
+        assert_eq!(redact("rcon_port", "25575"), "25575");
+
+        let servers = redact(
+            "servers",
+            r#"[{ id = "survival", rcon_password = "hunter2" }]"#,
+        );
+        assert!(!servers.contains("hunter2"));
+        assert!(servers.contains("survival"));
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use chrono::{DateTime, TimeDelta, Utc};
+use poise::serenity_prelude::{
+    ChannelId, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage, Http, UserId,
+};
+use sqlx::{MySql, Pool};
+use tracing::{error, info, warn};
+
+use crate::{
+    Error,
+    models::{config::Config, database::WhitelistRequest},
+    requests,
+};
+
+/// How often pending requests are checked for expiry and reminders.
+const PENDING_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Most requests listed in a single reminder, keeping the digest within Discord's limits.
+const REMINDER_REQUESTS_SHOWN: usize = 15;
+
+/// Expires requests that weren't decided within their server's `request_ttl_hours`, and reminds
+/// reviewers of requests that have been pending for a while.
+pub fn spawn_pending_worker(http: Arc<Http>, config: Arc<ArcSwap<Config>>, db: Pool<MySql>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PENDING_INTERVAL);
+        // When the last reminder was sent to each channel
+        let mut reminders_sent = HashMap::new();
+
+        loop {
+            interval.tick().await;
+            let config = config.load_full();
+
+            if let Err(err) = expire_requests(&http, &config, &db).await {
+                error!("Failed to expire pending requests: {err}");
+            }
+            if let Err(err) = send_reminders(&http, &config, &db, &mut reminders_sent).await {
+                error!("Failed to send reminders for pending requests: {err}");
+            }
+        }
+    });
+}
+
+async fn fetch_pending(db: &Pool<MySql>) -> Result<Vec<WhitelistRequest>, Error> {
+    let pending = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE status = ? ORDER BY created_at",
+        WhitelistRequest::PENDING
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(pending)
+}
+
+async fn expire_requests(http: &Http, config: &Config, db: &Pool<MySql>) -> Result<(), Error> {
+    let now = Utc::now();
+
+    for request in fetch_pending(db).await? {
+        let Some(server) = config.servers.iter().find(|s| s.id == request.server_id) else {
+            continue;
+        };
+        let (Some(ttl), Some(created_at)) = (server.request_ttl_hours(), request.created_at) else {
+            continue;
+        };
+        if now < created_at + TimeDelta::hours(ttl.into()) {
+            continue;
+        }
+
+        // Requests decided since they were fetched are left alone
+        let expired = requests::set_status(
+            db,
+            &request.id,
+            WhitelistRequest::PENDING,
+            WhitelistRequest::EXPIRED,
+            None,
+            Some(&format!("Not reviewed within {ttl} hours")),
+        )
+        .await?;
+        if !expired {
+            continue;
+        }
+        info!("Request `{}` expired after {ttl} hours", request.id);
+
+        let embed = CreateEmbed::new()
+            .title("⌛ Whitelist request expired")
+            .color(0x8c8fa1)
+            .description(format!(
+                "Whitelist request for <@{}> expired after {ttl} hours without a decision.\n\n**Request ID:** {}\n**Server ID:** {}\n**Minecraft Username:** {}",
+                request.discord_id, request.id, request.server_id, request.minecraft_username
+            ))
+            .footer(CreateEmbedFooter::new(format!("Expired at {now}")));
+        let message = EditMessage::new()
+            .content("")
+            .embed(embed)
+            .components(vec![]);
+        if let Err(err) = requests::edit_message(http, &request, message).await {
+            warn!(
+                "Failed to update the message of expired request `{}`: {err}",
+                request.id
+            );
+        }
+
+        let Ok(user_id) = request.discord_id.parse::<u64>() else {
+            continue;
+        };
+        let guild_id = request.guild_id.as_deref().and_then(|id| id.parse().ok());
+        let embed = CreateEmbed::new()
+            .title("⌛ Your whitelist request has expired")
+            .description(format!(
+                "Your whitelist request for the server _**{}**_ wasn't reviewed in time and has expired. Feel free to request it again!\n\n**Server ID:** `{}`\n**Minecraft Username:** `{}`",
+                server.name, server.id, request.minecraft_username
+            ))
+            .footer(requests::fetch_dm_footer(http, guild_id).await)
+            .color(0x8c8fa1);
+        if let Err(error) = UserId::new(user_id)
+            .dm(http, CreateMessage::new().add_embed(embed))
+            .await
+        {
+            error!("Error sending DM: {:?}", error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Requests to remind reviewers of in one channel.
+#[derive(Default)]
+struct Digest<'a> {
+    requests: Vec<&'a WhitelistRequest>,
+    ping_roles: BTreeSet<u64>,
+}
+
+async fn send_reminders(
+    http: &Http,
+    config: &Config,
+    db: &Pool<MySql>,
+    reminders_sent: &mut HashMap<u64, DateTime<Utc>>,
+) -> Result<(), Error> {
+    let now = Utc::now();
+    let pending = fetch_pending(db).await?;
+
+    let mut digests: BTreeMap<u64, Digest> = BTreeMap::new();
+    for request in &pending {
+        let guild_id = request.guild_id.as_deref().and_then(|id| id.parse().ok());
+        let Some(whitelist) = config.whitelist_for(guild_id) else {
+            continue;
+        };
+        let (Some(reminders), Some(server), Some(created_at)) = (
+            &whitelist.reminders,
+            config.servers.iter().find(|s| s.id == request.server_id),
+            request.created_at,
+        ) else {
+            continue;
+        };
+        if now - created_at < TimeDelta::hours(reminders.after_hours.into()) {
+            continue;
+        }
+
+        let channel = reminders
+            .channel
+            .unwrap_or_else(|| server.request_channel(whitelist));
+        let recently_reminded = reminders_sent
+            .get(&channel)
+            .is_some_and(|sent| now - *sent < TimeDelta::hours(reminders.interval_hours.into()));
+        if recently_reminded {
+            continue;
+        }
+
+        let digest = digests.entry(channel).or_default();
+        digest.requests.push(request);
+        digest.ping_roles.extend(server.ping_roles(whitelist));
+    }
+
+    for (channel, digest) in digests {
+        let mut lines: Vec<String> = digest
+            .requests
+            .iter()
+            .take(REMINDER_REQUESTS_SHOWN)
+            .map(|request| {
+                let requested_at = request
+                    .created_at
+                    .map(|t| format!(", requested <t:{}:R>", t.timestamp()))
+                    .unwrap_or_default();
+                format!(
+                    "- {} `{}` on `{}` by <@{}>{requested_at}",
+                    request.link(),
+                    request.minecraft_username,
+                    request.server_id,
+                    request.discord_id
+                )
+            })
+            .collect();
+        if digest.requests.len() > REMINDER_REQUESTS_SHOWN {
+            lines.push(format!(
+                "- _{} more_",
+                digest.requests.len() - REMINDER_REQUESTS_SHOWN
+            ));
+        }
+
+        let pings = digest
+            .ping_roles
+            .iter()
+            .map(|role_id| format!("<@&{}>", role_id))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let embed = CreateEmbed::new()
+            .title("⏰ Whitelist requests waiting for review")
+            .color(0xdf8e1d)
+            .description(format!(
+                "{} whitelist requests are still waiting for a decision:\n\n{}",
+                digest.requests.len(),
+                lines.join("\n")
+            ));
+
+        match ChannelId::new(channel)
+            .send_message(http, CreateMessage::new().content(pings).add_embed(embed))
+            .await
+        {
+            Ok(_) => {
+                reminders_sent.insert(channel, now);
+            }
+            Err(err) => warn!("Failed to send a reminder to channel `{channel}`: {err}"),
+        }
+    }
+
+    Ok(())
+}
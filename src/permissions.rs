@@ -0,0 +1,260 @@
+use std::fmt;
+
+use poise::serenity_prelude::Member;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Context, Error,
+    error::ApplicationError,
+    models::config::{Config, Server, WhitelistConfig},
+};
+
+/// Something a member can be allowed to do, granted to roles in the `permissions` section of the
+/// config or of a server.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize, JsonSchema,
+)]
+pub enum Permission {
+    /// Request to be whitelisted on a server. Everyone can by default.
+    #[serde(rename = "whitelist.request")]
+    WhitelistRequest,
+    /// Approve or deny whitelist requests. Also granted by the whitelist's `allowed_roles`.
+    #[serde(rename = "whitelist.review")]
+    WhitelistReview,
+    /// List servers and their players. Everyone can by default.
+    #[serde(rename = "server.view")]
+    ServerView,
+    /// View and change the config with `/config` and inspect permissions.
+    #[serde(rename = "config.manage")]
+    ConfigManage,
+    /// Reload the config and manage command registration with `/breeze`. Can only be granted in
+    /// the top-level `permissions`.
+    #[serde(rename = "bot.manage")]
+    BotManage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 5] = [
+        Permission::WhitelistRequest,
+        Permission::WhitelistReview,
+        Permission::ServerView,
+        Permission::ConfigManage,
+        Permission::BotManage,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::WhitelistRequest => "whitelist.request",
+            Permission::WhitelistReview => "whitelist.review",
+            Permission::ServerView => "server.view",
+            Permission::ConfigManage => "config.manage",
+            Permission::BotManage => "bot.manage",
+        }
+    }
+
+    /// Whether the permission applies to the whole bot rather than a server, so it can only be
+    /// granted in the top-level `permissions`.
+    pub fn is_global(&self) -> bool {
+        matches!(self, Permission::BotManage)
+    }
+
+    /// Whether everyone has this permission on servers where no roles are granted it.
+    pub fn public_by_default(&self) -> bool {
+        matches!(self, Permission::WhitelistRequest | Permission::ServerView)
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Returns the roles granted the permission on the given server, or on any server if `server`
+/// is `None`. Returns `None` if the permission isn't granted to any roles there, neither
+/// globally nor by the server, which leaves permissions that are public by default to everyone.
+/// On any server, that's the case as soon as one server leaves such a permission public.
+/// Servers can't grant [global](Permission::is_global) permissions.
+pub fn granted_roles(
+    config: &Config,
+    permission: Permission,
+    server: Option<&Server>,
+) -> Option<Vec<u64>> {
+    if permission.is_global() {
+        return config.permissions.get(&permission).cloned();
+    }
+
+    let configured_on = |s: &Server| s.permissions.contains_key(&permission);
+    let configured = config.permissions.contains_key(&permission)
+        || match server {
+            Some(server) => configured_on(server),
+            None if permission.public_by_default() => {
+                !config.servers.is_empty() && config.servers.iter().all(configured_on)
+            }
+            None => config.servers.iter().any(configured_on),
+        };
+    if !configured {
+        return None;
+    }
+
+    let servers = match server {
+        Some(server) => vec![server],
+        None => config.servers.iter().collect(),
+    };
+
+    let mut roles: Vec<u64> = config
+        .permissions
+        .get(&permission)
+        .into_iter()
+        .chain(
+            servers
+                .iter()
+                .filter_map(|s| s.permissions.get(&permission)),
+        )
+        .flatten()
+        .copied()
+        .collect();
+    roles.sort_unstable();
+    roles.dedup();
+
+    Some(roles)
+}
+
+/// Returns the roles allowed to review requests for the given server by the whitelist settings,
+/// or for any server if `server` is `None`.
+pub fn approver_roles(
+    config: &Config,
+    whitelist: &WhitelistConfig,
+    server: Option<&Server>,
+) -> Vec<u64> {
+    match server {
+        Some(server) => server.approver_roles(whitelist).to_vec(),
+        None => whitelist
+            .allowed_roles
+            .iter()
+            .chain(
+                config
+                    .servers
+                    .iter()
+                    .flat_map(|s| s.approver_roles(whitelist)),
+            )
+            .copied()
+            .collect(),
+    }
+}
+
+/// Returns whether the member has the permission on the given server, or on any server if
+/// `server` is `None`.
+///
+/// Administrators have every permission, except that `whitelist.review` follows the whitelist's
+/// `allow_admin` setting. The guild ID can be granted a permission to grant it to everyone.
+pub fn has_permission(
+    config: &Config,
+    member: &Member,
+    permission: Permission,
+    server: Option<&Server>,
+) -> bool {
+    let guild_id = member.guild_id.get();
+    let whitelist = config.whitelist_for(Some(guild_id));
+
+    let is_admin = member.permissions.is_some_and(|p| p.administrator());
+    if is_admin
+        && (permission != Permission::WhitelistReview || whitelist.is_none_or(|w| w.allow_admin))
+    {
+        return true;
+    }
+
+    let mut roles = match granted_roles(config, permission, server) {
+        Some(roles) => roles,
+        None if permission.public_by_default() => return true,
+        None => vec![],
+    };
+    if let (Permission::WhitelistReview, Some(whitelist)) = (permission, whitelist) {
+        roles.extend(approver_roles(config, whitelist, server));
+    }
+
+    roles.contains(&guild_id) || member.roles.iter().any(|r| roles.contains(&r.get()))
+}
+
+/// Fails with [ApplicationError::MissingPermission] unless the author of the command has the
+/// permission. Outside of guilds only permissions everyone has by default are granted.
+pub async fn require(
+    ctx: Context<'_>,
+    permission: Permission,
+    server: Option<&Server>,
+) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+
+    let allowed = match ctx.author_member().await {
+        Some(member) if ctx.guild_id().is_some() => {
+            has_permission(&config, &member, permission, server)
+        }
+        _ => permission.public_by_default() && granted_roles(&config, permission, None).is_none(),
+    };
+
+    match allowed {
+        true => Ok(()),
+        false => Err(ApplicationError::MissingPermission(permission).into()),
+    }
+}
+
+/// Fails with [ApplicationError::MissingPermission] unless the author of the command has the
+/// permission globally, rather than on some servers only. Used for settings that aren't tied to
+/// a server.
+pub async fn require_global(ctx: Context<'_>, permission: Permission) -> Result<(), Error> {
+    let config = ctx.data().config.load_full();
+
+    let allowed = match ctx.author_member().await {
+        Some(member) if ctx.guild_id().is_some() => {
+            let guild_id = member.guild_id.get();
+            let whitelist = config.whitelist_for(Some(guild_id));
+            let is_admin = member.permissions.is_some_and(|p| p.administrator());
+
+            match config.permissions.get(&permission) {
+                _ if is_admin
+                    && (permission != Permission::WhitelistReview
+                        || whitelist.is_none_or(|w| w.allow_admin)) =>
+                {
+                    true
+                }
+                Some(roles) => {
+                    roles.contains(&guild_id)
+                        || member.roles.iter().any(|r| roles.contains(&r.get()))
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    };
+
+    match allowed {
+        true => Ok(()),
+        false => Err(ApplicationError::MissingPermission(permission).into()),
+    }
+}
+
+pub async fn can_request_whitelist(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Permission::WhitelistRequest, None).await?;
+    Ok(true)
+}
+
+pub async fn can_view_servers(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Permission::ServerView, None).await?;
+    Ok(true)
+}
+
+pub async fn can_manage_config(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Permission::ConfigManage, None).await?;
+    Ok(true)
+}
+
+pub async fn can_manage_global_config(ctx: Context<'_>) -> Result<bool, Error> {
+    require_global(ctx, Permission::ConfigManage).await?;
+    Ok(true)
+}
+
+pub async fn can_manage_bot(ctx: Context<'_>) -> Result<bool, Error> {
+    require_global(ctx, Permission::BotManage).await?;
+    Ok(true)
+}
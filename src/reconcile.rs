@@ -0,0 +1,222 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use arc_swap::ArcSwap;
+use poise::serenity_prelude::{
+    self as serenity, ActionRowComponent, ButtonKind, ChannelId, CreateActionRow, EditMessage,
+    GetMessages, HttpError, Message, MessageId, StatusCode,
+};
+use sqlx::{MySql, Pool};
+use tracing::{error, info, warn};
+
+use crate::{
+    Error,
+    commands::servers::whitelist::{
+        create_request_buttons, create_request_embed, create_request_message,
+    },
+    models::{config::Config, database::WhitelistRequest},
+    requests,
+};
+
+/// How many of the latest messages in each request channel are checked for stale buttons.
+const SCANNED_MESSAGES: u8 = 100;
+
+/// Brings the staff messages back in line with their requests once in the background, since
+/// messages can be deleted and requests decided while the bot is offline.
+pub fn spawn_reconciler(http: Arc<serenity::Http>, config: Arc<ArcSwap<Config>>, db: Pool<MySql>) {
+    tokio::spawn(async move {
+        let config = config.load_full();
+
+        match repost_missing_messages(&http, &config, &db).await {
+            Ok(0) => {}
+            Ok(reposted) => info!("Re-posted {reposted} missing request messages"),
+            Err(err) => error!("Failed to re-post missing request messages: {err}"),
+        }
+        match disable_stale_buttons(&http, &config, &db).await {
+            Ok(0) => {}
+            Ok(disabled) => info!("Disabled the buttons of {disabled} stale request messages"),
+            Err(err) => error!("Failed to disable stale request buttons: {err}"),
+        }
+    });
+}
+
+fn is_not_found(err: &serenity::Error) -> bool {
+    matches!(
+        err,
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response))
+            if response.status_code == StatusCode::NOT_FOUND
+    )
+}
+
+/// Posts the staff message again for pending requests whose message was deleted or never
+/// recorded, returning how many were re-posted.
+async fn repost_missing_messages(
+    http: &serenity::Http,
+    config: &Config,
+    db: &Pool<MySql>,
+) -> Result<usize, Error> {
+    let pending = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE status = ? ORDER BY created_at",
+        WhitelistRequest::PENDING
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut reposted = 0;
+    for request in pending {
+        if let (Some(channel_id), Some(message_id)) = (&request.channel_id, &request.message_id) {
+            let (Ok(channel_id), Ok(message_id)) = (channel_id.parse(), message_id.parse()) else {
+                warn!("Request `{}` has an invalid message ID", request.id);
+                continue;
+            };
+            match ChannelId::new(channel_id)
+                .message(http, MessageId::new(message_id))
+                .await
+            {
+                Ok(_) => continue,
+                Err(err) if is_not_found(&err) => {}
+                Err(err) => {
+                    warn!(
+                        "Failed to fetch the message of request `{}`: {err}",
+                        request.id
+                    );
+                    continue;
+                }
+            }
+        }
+
+        let guild_id = request.guild_id.as_deref().and_then(|id| id.parse().ok());
+        let (Some(whitelist_config), Some(server)) = (
+            config.whitelist_for(guild_id),
+            config.servers.iter().find(|s| s.id == request.server_id),
+        ) else {
+            warn!(
+                "Can't re-post request `{}` since its server or whitelist settings were removed",
+                request.id
+            );
+            continue;
+        };
+
+        let votes = requests::fetch_votes(db, &request.id).await?;
+        let answers = requests::fetch_answers(db, &request.id).await?;
+        let conflicts = requests::find_conflicts(
+            db,
+            &request.id,
+            &request.discord_id,
+            &request.server_id,
+            &request.minecraft_username,
+        )
+        .await?;
+        let embed = create_request_embed(&request, server, &votes, &answers, &conflicts);
+
+        let message = match ChannelId::new(server.request_channel(whitelist_config))
+            .send_message(
+                http,
+                create_request_message(&request, server, whitelist_config, embed),
+            )
+            .await
+        {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Failed to re-post request `{}`: {err}", request.id);
+                continue;
+            }
+        };
+        requests::set_message(
+            db,
+            &request.id,
+            &message.channel_id.to_string(),
+            &message.id.to_string(),
+        )
+        .await?;
+        reposted += 1;
+    }
+
+    Ok(reposted)
+}
+
+/// Returns the ID of the request whose enabled buttons are on the message, if any.
+fn request_with_buttons(message: &Message) -> Option<&str> {
+    message
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::Button(button) if !button.disabled => match &button.data {
+                ButtonKind::NonLink { custom_id, .. } => custom_id
+                    .strip_prefix("wlreq-approve-")
+                    .or_else(|| custom_id.strip_prefix("wlreq-deny-")),
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+/// Disables the buttons of recent messages in the request channels whose requests are no longer
+/// pending or don't exist anymore, returning how many messages were changed.
+async fn disable_stale_buttons(
+    http: &serenity::Http,
+    config: &Config,
+    db: &Pool<MySql>,
+) -> Result<usize, Error> {
+    let bot_id = http.get_current_user().await?.id;
+
+    let channels: BTreeSet<u64> = config
+        .whitelist
+        .iter()
+        .chain(config.guilds.iter().filter_map(|g| g.whitelist.as_ref()))
+        .map(|w| w.request_channel)
+        .chain(
+            config
+                .servers
+                .iter()
+                .filter_map(|s| s.whitelist.as_ref().and_then(|w| w.request_channel)),
+        )
+        .collect();
+
+    let mut disabled = 0;
+    for channel_id in channels {
+        let channel_id = ChannelId::new(channel_id);
+        let messages = match channel_id
+            .messages(http, GetMessages::new().limit(SCANNED_MESSAGES))
+            .await
+        {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!("Failed to fetch the messages of channel `{channel_id}`: {err}");
+                continue;
+            }
+        };
+
+        for message in messages.iter().filter(|m| m.author.id == bot_id) {
+            let Some(request_id) = request_with_buttons(message) else {
+                continue;
+            };
+
+            let pending = sqlx::query!(
+                "SELECT EXISTS(SELECT 1 FROM whitelist_request WHERE id = ? AND status = ?) as 'pending'",
+                request_id,
+                WhitelistRequest::PENDING
+            )
+            .fetch_one(db)
+            .await?
+            .pending
+                > 0;
+            if pending {
+                continue;
+            }
+
+            let edit = EditMessage::new().components(vec![CreateActionRow::Buttons(
+                create_request_buttons(request_id, true),
+            )]);
+            match channel_id.edit_message(http, message.id, edit).await {
+                Ok(_) => disabled += 1,
+                Err(err) => warn!(
+                    "Failed to disable the buttons of request `{request_id}`'s message: {err}"
+                ),
+            }
+        }
+    }
+
+    Ok(disabled)
+}
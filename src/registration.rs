@@ -0,0 +1,52 @@
+use poise::serenity_prelude::{self as serenity, GuildId};
+use tracing::info;
+
+use crate::{Data, Error, error::ApplicationError, models::config::CommandsConfig};
+
+/// Registers the bot's slash commands where the config says to.
+///
+/// In dev mode commands are only registered in `commands.dev_guild`, so changes show up
+/// immediately without touching the global command list.
+///
+/// Returns a description of where the commands were registered.
+pub async fn register_commands(
+    http: impl AsRef<serenity::Http>,
+    commands: &[poise::Command<Data, Error>],
+    config: &CommandsConfig,
+    dev_mode: bool,
+) -> Result<String, Error> {
+    let http = http.as_ref();
+
+    if dev_mode {
+        let guild_id = config.dev_guild.filter(|id| *id != 0).ok_or_else(|| {
+            ApplicationError::InvalidConfig(
+                "dev mode requires `commands.dev_guild` to be set".to_string(),
+            )
+        })?;
+
+        poise::builtins::register_in_guild(http, commands, GuildId::new(guild_id)).await?;
+        info!("Registered commands in dev guild `{guild_id}`");
+
+        return Ok(format!("in dev guild `{guild_id}`"));
+    }
+
+    let mut targets = vec![];
+
+    if config.register_globally {
+        poise::builtins::register_globally(http, commands).await?;
+        targets.push("globally".to_string());
+    }
+
+    for guild_id in config.guilds.iter().filter(|id| **id != 0) {
+        poise::builtins::register_in_guild(http, commands, GuildId::new(*guild_id)).await?;
+        targets.push(format!("in guild `{guild_id}`"));
+    }
+
+    let registered = match targets.is_empty() {
+        true => "nowhere".to_string(),
+        false => targets.join(", "),
+    };
+    info!("Registered commands {registered}");
+
+    Ok(registered)
+}
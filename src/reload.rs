@@ -0,0 +1,214 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use notify::{RecursiveMode, Watcher};
+use sqlx::{MySql, Pool};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::{Mutex, mpsc},
+};
+use tracing::{error, info, warn};
+
+use crate::{error::ApplicationError, models::config::Config, overrides};
+
+/// How long to wait for more file events before reloading, since editors tend to write a file
+/// in several steps.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Held while the config is rebuilt and swapped in, so a reload can't overwrite a runtime change
+/// made at the same time, or the other way around.
+pub static CONFIG_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Summary of what changed between two configs.
+#[derive(Debug, Default)]
+pub struct ConfigDiff {
+    pub servers_added: Vec<String>,
+    pub servers_removed: Vec<String>,
+    /// Servers with any changed setting, including their `permissions` and `approver_roles`
+    pub servers_changed: Vec<String>,
+    pub whitelist_changed: bool,
+    pub guilds_changed: bool,
+    pub groups_changed: bool,
+    pub permissions_changed: bool,
+    pub commands_changed: bool,
+}
+
+impl ConfigDiff {
+    pub fn new(old: &Config, new: &Config) -> Self {
+        let mut diff = ConfigDiff {
+            whitelist_changed: old.whitelist != new.whitelist,
+            guilds_changed: old.guilds != new.guilds,
+            groups_changed: old.groups != new.groups,
+            permissions_changed: old.permissions != new.permissions,
+            commands_changed: old.commands != new.commands,
+            ..Default::default()
+        };
+
+        for server in &new.servers {
+            match old.servers.iter().find(|s| s.id == server.id) {
+                Some(old_server) if old_server != server => {
+                    diff.servers_changed.push(server.id.clone())
+                }
+                Some(_) => {}
+                None => diff.servers_added.push(server.id.clone()),
+            }
+        }
+
+        for server in &old.servers {
+            if !new.servers.iter().any(|s| s.id == server.id) {
+                diff.servers_removed.push(server.id.clone());
+            }
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers_added.is_empty()
+            && self.servers_removed.is_empty()
+            && self.servers_changed.is_empty()
+            && !self.whitelist_changed
+            && !self.guilds_changed
+            && !self.groups_changed
+            && !self.permissions_changed
+            && !self.commands_changed
+    }
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No changes");
+        }
+
+        let format_ids = |ids: &[String]| {
+            ids.iter()
+                .map(|id| format!("`{id}`"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        let mut lines = vec![];
+        if !self.servers_added.is_empty() {
+            lines.push(format!(
+                "**Servers added:** {}",
+                format_ids(&self.servers_added)
+            ));
+        }
+        if !self.servers_removed.is_empty() {
+            lines.push(format!(
+                "**Servers removed:** {}",
+                format_ids(&self.servers_removed)
+            ));
+        }
+        if !self.servers_changed.is_empty() {
+            lines.push(format!(
+                "**Servers changed:** {}",
+                format_ids(&self.servers_changed)
+            ));
+        }
+        if self.whitelist_changed {
+            lines.push("**Whitelist settings changed**".to_string());
+        }
+        if self.guilds_changed {
+            lines.push("**Guild settings changed**".to_string());
+        }
+        if self.groups_changed {
+            lines.push("**Server groups changed**".to_string());
+        }
+        if self.permissions_changed {
+            lines.push("**Permissions changed**".to_string());
+        }
+        if self.commands_changed {
+            lines.push("**Command registration changed**".to_string());
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Loads and validates the config file with the overrides from the database, swapping it in
+/// only if it's valid.
+///
+/// If loading fails the current config stays live and the error is returned.
+pub async fn reload(
+    config: &ArcSwap<Config>,
+    db: &Pool<MySql>,
+) -> Result<ConfigDiff, ApplicationError> {
+    let _lock = CONFIG_LOCK.lock().await;
+    let overrides = overrides::fetch(db).await?;
+    let new_config = Config::load(&overrides)?;
+    let diff = ConfigDiff::new(&config.load(), &new_config);
+    config.store(Arc::new(new_config));
+    Ok(diff)
+}
+
+async fn reload_and_log(config: &ArcSwap<Config>, db: &Pool<MySql>, trigger: &str) {
+    match reload(config, db).await {
+        Ok(diff) if diff.is_empty() => info!("Reloaded config ({trigger}), nothing changed"),
+        Ok(diff) => info!("Reloaded config ({trigger}):\n{diff}"),
+        Err(err) => error!("Failed to reload config ({trigger}), keeping old config: {err}"),
+    }
+}
+
+/// Reloads the config whenever the process receives `SIGHUP`.
+pub fn spawn_sighup_handler(
+    config: Arc<ArcSwap<Config>>,
+    db: Pool<MySql>,
+) -> Result<(), ApplicationError> {
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            reload_and_log(&config, &db, "SIGHUP").await;
+        }
+    });
+
+    Ok(())
+}
+
+/// Reloads the config whenever the config file changes.
+///
+/// The parent directory is watched rather than the file itself, since editors and mounted
+/// volumes often replace the file instead of writing to it.
+pub fn spawn_file_watcher(
+    config: Arc<ArcSwap<Config>>,
+    db: Pool<MySql>,
+) -> Result<(), ApplicationError> {
+    let config_path = std::path::absolute(Config::path())?;
+    let watch_dir = config_path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| ".".into());
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let watched_path = config_path.clone();
+    let mut watcher =
+        notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) => {
+                if (event.kind.is_modify() || event.kind.is_create())
+                    && event.paths.iter().any(|p| p == &watched_path)
+                {
+                    let _ = tx.send(());
+                }
+            }
+            Err(err) => warn!("Config watcher error: {err}"),
+        })?;
+    watcher.watch(&watch_dir, RecursiveMode::NonRecursive)?;
+
+    tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(WATCH_DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+
+            reload_and_log(&config, &db, "file changed").await;
+        }
+    });
+
+    info!("Watching {} for changes", config_path.display());
+
+    Ok(())
+}
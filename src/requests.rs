@@ -0,0 +1,1206 @@
+use std::{sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use bollard::{Docker, secret::ContainerStateStatusEnum};
+use chrono::{DateTime, TimeDelta, Utc};
+use poise::serenity_prelude::{
+    ChannelId, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage, GuildId, Http, Member,
+    MessageId, UserId,
+};
+use regex::Regex;
+use sqlx::{MySql, Pool};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    Error, actions,
+    docker::ContainerResolver,
+    error::ApplicationError,
+    models::{
+        config::{
+            AutoApproveRule, Config, EligibilityConfig, Server, UsernameValidation, WhitelistConfig,
+        },
+        database::{WhitelistAnswer, WhitelistRequest, WhitelistVote},
+    },
+    utils,
+};
+
+/// How often approvals queued until their server is running are retried.
+const QUEUE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Seconds in a day.
+const DAY: i64 = 24 * 60 * 60;
+
+pub fn create_dm_footer(guild_name: String, guild_icon: Option<String>) -> CreateEmbedFooter {
+    CreateEmbedFooter::new(format!("From server {guild_name}")).icon_url(
+        guild_icon.unwrap_or("https://files.jadelily.dev/ZabtFsxYPYvgO2bpKry3.png".to_string()),
+    )
+}
+
+/// Creates the DM footer for the guild a request was made in, for when it isn't at hand.
+pub async fn fetch_dm_footer(http: &Http, guild_id: Option<u64>) -> CreateEmbedFooter {
+    match guild_id {
+        Some(guild_id) => match GuildId::new(guild_id).to_partial_guild(http).await {
+            Ok(guild) => create_dm_footer(guild.name.clone(), guild.icon_url()),
+            Err(_) => create_dm_footer(guild_id.to_string(), None),
+        },
+        None => create_dm_footer("breeze".to_string(), None),
+    }
+}
+
+/// Returns whether the server's container is running.
+pub async fn is_running(
+    containers: &ContainerResolver,
+    server: &Server,
+) -> Result<bool, ApplicationError> {
+    let docker = Docker::connect_with_defaults()?;
+    let container = containers.inspect(&docker, server).await?;
+
+    Ok(container.state.and_then(|state| state.status) == Some(ContainerStateStatusEnum::RUNNING))
+}
+
+/// Which edition of Minecraft a player plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Edition {
+    #[name = "Java"]
+    Java,
+    #[name = "Bedrock"]
+    Bedrock,
+}
+
+impl Edition {
+    /// Tells which edition a whitelisted username belongs to by the server's Floodgate prefix.
+    pub fn of(server: &Server, minecraft_username: &str) -> Edition {
+        match server.bedrock() {
+            Some(bedrock) if minecraft_username.starts_with(&bedrock.prefix) => Edition::Bedrock,
+            _ => Edition::Java,
+        }
+    }
+}
+
+/// Longest username accepted by any strategy, leaving room for Floodgate prefixes and the
+/// usernames of auth proxies.
+const MAX_USERNAME_LENGTH: usize = 32;
+
+/// Whether the username is safe to put in an RCON command: short, and only made of letters,
+/// digits, underscores, dots and dashes.
+fn is_safe_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= MAX_USERNAME_LENGTH
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Checks that the account exists and returns the username it's whitelisted as, which for
+/// Bedrock players is their gamertag behind the server's Floodgate prefix.
+pub async fn resolve_username(
+    whitelist_config: &WhitelistConfig,
+    server: &Server,
+    edition: Edition,
+    input: &str,
+) -> Result<String, Error> {
+    let input = input.trim();
+
+    match edition {
+        Edition::Java => {
+            // Usernames end up in RCON commands, so they're checked for safety by every strategy
+            let valid = is_safe_username(input)
+                && match server.username_validation(whitelist_config) {
+                    UsernameValidation::Mojang => utils::validate_minecraft_username(input).await?,
+                    UsernameValidation::Regex => server.username_pattern()?.is_match(input),
+                    UsernameValidation::Offline => {
+                        Regex::new(Server::DEFAULT_USERNAME_PATTERN)?.is_match(input)
+                    }
+                    UsernameValidation::None => true,
+                };
+            if !valid {
+                return Err(format!(
+                    "Invalid Minecraft username `{input}`. Please make sure it's entered correctly."
+                )
+                .into());
+            }
+
+            Ok(input.to_string())
+        }
+        Edition::Bedrock => {
+            let bedrock = server.bedrock().ok_or(format!(
+                "Server `{}` doesn't accept Bedrock players",
+                server.id
+            ))?;
+            let gamertag = input.strip_prefix(&bedrock.prefix).unwrap_or(input);
+            if !is_safe_username(&gamertag.replace(' ', "_")) {
+                return Err(format!(
+                    "Invalid Bedrock gamertag `{gamertag}`. Please make sure it's entered correctly."
+                )
+                .into());
+            }
+
+            // Only servers validating against Mojang check gamertags against Xbox Live
+            let online = server.username_validation(whitelist_config) == UsernameValidation::Mojang;
+            if online
+                && utils::lookup_bedrock_xuid(&bedrock.xuid_lookup_url, gamertag)
+                    .await?
+                    .is_none()
+            {
+                return Err(format!(
+                    "Invalid Bedrock gamertag `{gamertag}`. Please make sure it's entered correctly."
+                )
+                .into());
+            }
+
+            // Floodgate replaces spaces in gamertags with underscores
+            Ok(format!("{}{}", bedrock.prefix, gamertag.replace(' ', "_")))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum WhitelistAction {
+    Add,
+    Remove,
+}
+
+/// Returns the RCON command adding or removing the player on the server, from the server's
+/// templates or Floodgate's for Bedrock players.
+async fn whitelist_command(
+    server: &Server,
+    minecraft_username: &str,
+    action: WhitelistAction,
+) -> Result<String, Error> {
+    if let Some(bedrock) = server.bedrock() {
+        if let Some(gamertag) = minecraft_username.strip_prefix(&bedrock.prefix) {
+            let template = match action {
+                WhitelistAction::Add => &bedrock.whitelist_add,
+                WhitelistAction::Remove => &bedrock.whitelist_remove,
+            };
+            return Ok(template
+                .replace("{gamertag}", gamertag)
+                .replace("{username}", minecraft_username));
+        }
+    }
+
+    let template = match action {
+        WhitelistAction::Add => server.whitelist_add_command(),
+        WhitelistAction::Remove => server.whitelist_remove_command(),
+    };
+    let mut command = template.replace("{username}", minecraft_username);
+    if command.contains("{uuid}") {
+        let uuid = match server.is_offline() {
+            true => utils::offline_uuid(minecraft_username),
+            false => {
+                let uuid = utils::lookup_minecraft_uuid(minecraft_username)
+                    .await?
+                    .ok_or(format!(
+                        "There's no Minecraft account called `{minecraft_username}`"
+                    ))?;
+                Uuid::parse_str(&uuid)?
+            }
+        };
+        command = command.replace("{uuid}", &uuid.to_string());
+    }
+
+    Ok(command)
+}
+
+/// Adds the player to the whitelist of the server, or of every server sharing its whitelist,
+/// over RCON.
+pub async fn whitelist_player(
+    config: &Config,
+    server: &Server,
+    minecraft_username: &str,
+) -> Result<(), Error> {
+    for target in config.whitelist_targets(server) {
+        let mut rcon_client = utils::create_rcon_client(
+            &target.address,
+            target.rcon_port,
+            target.rcon_password.expose().to_string(),
+        )
+        .await?;
+
+        let command = whitelist_command(target, minecraft_username, WhitelistAction::Add).await?;
+        rcon_client
+            .run_command(&command)
+            .await
+            .map_err(|err| format!("Failed to whitelist on `{}`: {err}", target.id))?;
+    }
+
+    Ok(())
+}
+
+/// Removes the player from the whitelist of the server, or of every server sharing its
+/// whitelist, over RCON, kicking them with the given message in case they're online.
+pub async fn unwhitelist_player(
+    config: &Config,
+    server: &Server,
+    minecraft_username: &str,
+    kick_message: Option<&str>,
+) -> Result<(), Error> {
+    for target in config.whitelist_targets(server) {
+        let mut rcon_client = utils::create_rcon_client(
+            &target.address,
+            target.rcon_port,
+            target.rcon_password.expose().to_string(),
+        )
+        .await?;
+
+        let command =
+            whitelist_command(target, minecraft_username, WhitelistAction::Remove).await?;
+        rcon_client
+            .run_command(&command)
+            .await
+            .map_err(|err| format!("Failed to unwhitelist on `{}`: {err}", target.id))?;
+        if let Some(message) = kick_message {
+            rcon_client
+                .run_command(
+                    &target
+                        .kick_command()
+                        .replace("{username}", minecraft_username)
+                        .replace("{message}", message),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the players on the server's whitelist over RCON. For servers sharing a whitelist, the
+/// first server it's applied on is asked.
+pub async fn list_whitelist(config: &Config, server: &Server) -> Result<Vec<String>, Error> {
+    let server = config.whitelist_targets(server)[0];
+    let mut rcon_client = utils::create_rcon_client(
+        &server.address,
+        server.rcon_port,
+        server.rcon_password.expose().to_string(),
+    )
+    .await?;
+
+    // e.g. "There are 2 whitelisted player(s): Notch, jeb_" or "There are no whitelisted players"
+    let response = rcon_client.run_command("whitelist list").await?;
+    let players = match response.split_once(':') {
+        Some((_, players)) => players
+            .split(',')
+            .map(|player| player.trim().to_string())
+            .filter(|player| !player.is_empty())
+            .collect(),
+        None => vec![],
+    };
+
+    Ok(players)
+}
+
+/// Whether the request was made in the guild. Requests from before guilds were recorded are
+/// attributed to the only guild when there aren't several.
+pub fn made_in_guild(config: &Config, request: &WhitelistRequest, guild_id: u64) -> bool {
+    match &request.guild_id {
+        Some(id) => *id == guild_id.to_string(),
+        None => config.guilds.is_empty(),
+    }
+}
+
+/// Moves a request from the `from` status to `status`. Returns whether it still had the `from`
+/// status, so only one of several decisions made at once goes through.
+pub async fn set_status(
+    db: &Pool<MySql>,
+    request_id: &str,
+    from: &str,
+    status: &str,
+    decided_by: Option<&str>,
+    reason: Option<&str>,
+) -> Result<bool, ApplicationError> {
+    let result = sqlx::query!(
+        "
+        UPDATE whitelist_request
+        SET status = ?, decided_by = ?, decided_at = CURRENT_TIMESTAMP, reason = ?
+        WHERE id = ? AND status = ?
+        ",
+        status,
+        decided_by,
+        reason,
+        request_id,
+        from
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Puts an approved request back up for review, when the player couldn't be whitelisted.
+pub async fn reopen(db: &Pool<MySql>, request_id: &str) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "
+        UPDATE whitelist_request
+        SET status = ?, decided_by = NULL, decided_at = NULL
+        WHERE id = ? AND status = ?
+        ",
+        WhitelistRequest::PENDING,
+        request_id,
+        WhitelistRequest::APPROVED
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Fetches a request made by the given member.
+pub async fn fetch_own_request(
+    db: &Pool<MySql>,
+    request_id: &str,
+    discord_id: &str,
+) -> Result<Option<WhitelistRequest>, ApplicationError> {
+    let request = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE id = ? AND discord_id = ?",
+        request_id,
+        discord_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(request)
+}
+
+pub async fn set_username(
+    db: &Pool<MySql>,
+    request_id: &str,
+    minecraft_username: &str,
+) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "UPDATE whitelist_request SET minecraft_username = ? WHERE id = ?",
+        minecraft_username,
+        request_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_votes(
+    db: &Pool<MySql>,
+    request_id: &str,
+) -> Result<Vec<WhitelistVote>, ApplicationError> {
+    let votes = sqlx::query_as!(
+        WhitelistVote,
+        "
+        SELECT request_id, reviewer_id, vote, created_at, duration_minutes
+        FROM whitelist_vote
+        WHERE request_id = ?
+        ORDER BY created_at
+        ",
+        request_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(votes)
+}
+
+/// Marks an approved, queued or revoking request as revoked, keeping who approved it and when.
+/// Returns whether it was still active, so a request revoked twice at once is only handled once.
+pub async fn revoke(
+    db: &Pool<MySql>,
+    request_id: &str,
+    reason: &str,
+) -> Result<bool, ApplicationError> {
+    let result = sqlx::query!(
+        "
+        UPDATE whitelist_request
+        SET status = ?, revoked_at = CURRENT_TIMESTAMP, reason = ?
+        WHERE id = ? AND status IN (?, ?, ?)
+        ",
+        WhitelistRequest::REVOKED,
+        reason,
+        request_id,
+        WhitelistRequest::APPROVED,
+        WhitelistRequest::QUEUED,
+        WhitelistRequest::REVOKING
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Marks an approved request as being revoked until the player is removed from the whitelist.
+/// Returns whether it was still approved, like [revoke].
+pub async fn begin_revoke(
+    db: &Pool<MySql>,
+    request_id: &str,
+    reason: &str,
+) -> Result<bool, ApplicationError> {
+    let result = sqlx::query!(
+        "UPDATE whitelist_request SET status = ?, reason = ? WHERE id = ? AND status = ?",
+        WhitelistRequest::REVOKING,
+        reason,
+        request_id,
+        WhitelistRequest::APPROVED
+    )
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Starts the clock on a request that was just approved, for the given duration or the server's
+/// `access_hours`. Returns when the access ends, or `None` if it's permanent or the request
+/// already had an end set.
+pub async fn start_access_timer(
+    db: &Pool<MySql>,
+    request_id: &str,
+    server: &Server,
+    duration: Option<TimeDelta>,
+) -> Result<Option<DateTime<Utc>>, ApplicationError> {
+    let duration = duration.or_else(|| {
+        server
+            .access_hours()
+            .map(|hours| TimeDelta::hours(hours.into()))
+    });
+    let Some(duration) = duration else {
+        return Ok(None);
+    };
+
+    // Access granted with an explicit duration keeps it
+    let expires_at = Utc::now() + duration;
+    let result = sqlx::query!(
+        "UPDATE whitelist_request SET expires_at = ? WHERE id = ? AND expires_at IS NULL",
+        expires_at,
+        request_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok((result.rows_affected() > 0).then_some(expires_at))
+}
+
+pub async fn record_vote(
+    db: &Pool<MySql>,
+    request_id: &str,
+    reviewer_id: &str,
+    vote: &str,
+    duration: Option<TimeDelta>,
+) -> Result<(), ApplicationError> {
+    let duration_minutes = duration.map(|duration| duration.num_minutes());
+    sqlx::query!(
+        "
+        INSERT INTO whitelist_vote (request_id, reviewer_id, vote, duration_minutes)
+        VALUES ( ?, ?, ?, ? )
+        ",
+        request_id,
+        reviewer_id,
+        vote,
+        duration_minutes
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Lets the requester know they've been whitelisted. Failing to send the DM is only logged,
+/// since members can have DMs disabled.
+pub async fn send_approval_dm(
+    http: &Http,
+    request: &WhitelistRequest,
+    server: &Server,
+    footer: CreateEmbedFooter,
+) {
+    let Ok(user_id) = request.discord_id.parse::<u64>() else {
+        return;
+    };
+
+    let mut description = format!(
+        "Your whitelist request for the server _**{}**_ has been approved!\n\n**Server ID:** `{}`\n**Minecraft Username:** `{}`",
+        server.name, server.id, request.minecraft_username
+    );
+    let expires_at = request.expires_at.or_else(|| {
+        server
+            .access_hours()
+            .map(|hours| Utc::now() + TimeDelta::hours(hours.into()))
+    });
+    if let Some(expires_at) = expires_at {
+        description.push_str(&format!(
+            "\n**Access ends:** <t:{}:R>",
+            expires_at.timestamp()
+        ));
+    }
+
+    let embed = CreateEmbed::new()
+        .title("✅ Your whitelist request has been approved!")
+        .description(description)
+        .footer(footer)
+        .color(0x40a02b);
+
+    if let Err(error) = UserId::new(user_id)
+        .dm(http, CreateMessage::new().add_embed(embed))
+        .await
+    {
+        error!("Error sending DM: {:?}", error);
+    }
+}
+
+/// Returns a description of the first auto-approve rule the request matches, if any.
+pub async fn match_auto_approve_rule(
+    db: &Pool<MySql>,
+    config: &Config,
+    server: &Server,
+    rules: &[AutoApproveRule],
+    member: &Member,
+    minecraft_username: &str,
+) -> Result<Option<String>, ApplicationError> {
+    let discord_id = member.user.id.to_string();
+
+    for (i, rule) in rules.iter().enumerate() {
+        if !rule.has_conditions() {
+            continue;
+        }
+
+        let mut matched = vec![];
+
+        if let Some(roles) = &rule.roles {
+            match member.roles.iter().find(|r| roles.contains(&r.get())) {
+                Some(role) => matched.push(format!("has role <@&{role}>")),
+                None => continue,
+            }
+        }
+
+        if let Some(days) = rule.min_membership_days {
+            let member_for = member
+                .joined_at
+                .map(|joined_at| Utc::now().timestamp() - joined_at.unix_timestamp());
+            match member_for {
+                Some(seconds) if seconds >= i64::from(days) * DAY => {
+                    matched.push(format!("member for at least {days} days"))
+                }
+                _ => continue,
+            }
+        }
+
+        if rule.linked_account {
+            let approvals = sqlx::query!(
+                "
+                SELECT COUNT(*) as 'count'
+                FROM whitelist_request
+                WHERE discord_id = ? AND minecraft_username = ? AND status = ? AND decided_by IS NOT NULL
+                ",
+                discord_id,
+                minecraft_username,
+                WhitelistRequest::APPROVED
+            )
+            .fetch_one(db)
+            .await?
+            .count;
+
+            if approvals == 0 {
+                continue;
+            }
+            matched.push("linked Minecraft account".to_string());
+        }
+
+        if rule.whitelisted_in_group {
+            let Some(group) = &server.group else {
+                continue;
+            };
+
+            let approved_on = sqlx::query!(
+                "
+                SELECT server_id
+                FROM whitelist_request
+                WHERE discord_id = ? AND minecraft_username = ? AND status = ?
+                ",
+                discord_id,
+                minecraft_username,
+                WhitelistRequest::APPROVED
+            )
+            .fetch_all(db)
+            .await?;
+
+            let group_server = approved_on.iter().find(|record| {
+                record.server_id != server.id
+                    && config
+                        .servers
+                        .iter()
+                        .any(|s| s.id == record.server_id && s.group.as_ref() == Some(group))
+            });
+            match group_server {
+                Some(record) => matched.push(format!("whitelisted on `{}`", record.server_id)),
+                None => continue,
+            }
+        }
+
+        return Ok(Some(format!("rule {}: {}", i + 1, matched.join(", "))));
+    }
+
+    Ok(None)
+}
+
+/// Another request that conflicts with a new one.
+#[derive(Debug)]
+pub struct Conflict {
+    pub request: WhitelistRequest,
+    pub reason: &'static str,
+}
+
+impl Conflict {
+    /// Whether the conflicting request is the same request made again.
+    pub fn is_repeat(&self) -> bool {
+        self.reason == REPEATED_REQUEST
+    }
+}
+
+const REPEATED_REQUEST: &str = "Same request is already pending";
+
+/// Finds open or approved requests that conflict with the given one: other requests by the same
+/// member for the same server, and other members' requests for the same Minecraft account.
+pub async fn find_conflicts(
+    db: &Pool<MySql>,
+    request_id: &str,
+    discord_id: &str,
+    server_id: &str,
+    minecraft_username: &str,
+) -> Result<Vec<Conflict>, ApplicationError> {
+    let candidates = sqlx::query_as!(
+        WhitelistRequest,
+        "
+        SELECT *
+        FROM whitelist_request
+        WHERE id <> ?
+            AND status IN (?, ?, ?)
+            AND ((discord_id = ? AND server_id = ?) OR minecraft_username = ?)
+        ORDER BY created_at
+        ",
+        request_id,
+        WhitelistRequest::PENDING,
+        WhitelistRequest::QUEUED,
+        WhitelistRequest::APPROVED,
+        discord_id,
+        server_id,
+        minecraft_username
+    )
+    .fetch_all(db)
+    .await?;
+
+    let conflicts = candidates
+        .into_iter()
+        .filter_map(|request| {
+            let reason = conflict_reason(&request, discord_id, server_id, minecraft_username)?;
+            Some(Conflict { request, reason })
+        })
+        .collect();
+
+    Ok(conflicts)
+}
+
+/// Says why an open or approved request conflicts with a new one, if it does.
+fn conflict_reason(
+    request: &WhitelistRequest,
+    discord_id: &str,
+    server_id: &str,
+    minecraft_username: &str,
+) -> Option<&'static str> {
+    let same_member = request.discord_id == discord_id;
+    let same_server = request.server_id == server_id;
+    let same_account = request
+        .minecraft_username
+        .eq_ignore_ascii_case(minecraft_username);
+    let pending = request.status == WhitelistRequest::PENDING;
+
+    match (same_member, same_server, same_account) {
+        (true, true, true) if pending => Some(REPEATED_REQUEST),
+        (true, true, true) => Some("Already whitelisted on this server"),
+        (true, true, false) if pending => Some("Pending request for another account"),
+        (true, true, false) => Some("Already whitelisted with another account"),
+        (false, _, true) => Some("Account claimed by another member"),
+        // The same account on another server
+        _ => None,
+    }
+}
+
+/// Lists the conflicts for the staff message.
+pub fn format_conflicts(conflicts: &[Conflict]) -> String {
+    // Embed fields are limited to 1024 characters
+    const SHOWN: usize = 4;
+
+    let mut lines: Vec<String> = conflicts
+        .iter()
+        .take(SHOWN)
+        .map(|conflict| {
+            format!(
+                "- {}: {} by <@{}> (`{}` on `{}`, {})",
+                conflict.reason,
+                conflict.request.link(),
+                conflict.request.discord_id,
+                conflict.request.minecraft_username,
+                conflict.request.server_id,
+                conflict.request.status
+            )
+        })
+        .collect();
+    if conflicts.len() > SHOWN {
+        lines.push(format!("- _{} more_", conflicts.len() - SHOWN));
+    }
+
+    lines.join("\n")
+}
+
+pub async fn set_message(
+    db: &Pool<MySql>,
+    request_id: &str,
+    channel_id: &str,
+    message_id: &str,
+) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "UPDATE whitelist_request SET channel_id = ?, message_id = ? WHERE id = ?",
+        channel_id,
+        message_id,
+        request_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Edits the staff message of the request, if it was recorded.
+pub async fn edit_message(
+    http: &Http,
+    request: &WhitelistRequest,
+    message: EditMessage,
+) -> Result<(), Error> {
+    let (Some(channel_id), Some(message_id)) = (&request.channel_id, &request.message_id) else {
+        return Ok(());
+    };
+
+    ChannelId::new(channel_id.parse()?)
+        .edit_message(http, MessageId::new(message_id.parse()?), message)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn save_answers(
+    db: &Pool<MySql>,
+    answers: &[WhitelistAnswer],
+) -> Result<(), ApplicationError> {
+    for answer in answers {
+        sqlx::query!(
+            "INSERT INTO whitelist_answer (request_id, position, question, answer) VALUES (?, ?, ?, ?)",
+            answer.request_id,
+            answer.position,
+            answer.question,
+            answer.answer
+        )
+        .execute(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn fetch_answers(
+    db: &Pool<MySql>,
+    request_id: &str,
+) -> Result<Vec<WhitelistAnswer>, ApplicationError> {
+    let answers = sqlx::query_as!(
+        WhitelistAnswer,
+        "SELECT * FROM whitelist_answer WHERE request_id = ? ORDER BY position",
+        request_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(answers)
+}
+
+/// Checks the member against the eligibility gates, returning which gate blocks them from
+/// requesting whitelisting on the server, if any. The request with the given ID, which is being
+/// made or edited, doesn't count towards the limits.
+pub async fn check_eligibility(
+    db: &Pool<MySql>,
+    eligibility: &EligibilityConfig,
+    server: &Server,
+    member: &Member,
+    request_id: &str,
+    minecraft_username: &str,
+) -> Result<Option<String>, Error> {
+    let now = Utc::now().timestamp();
+    let discord_id = member.user.id.to_string();
+
+    if let Some(days) = eligibility.min_account_age_days {
+        let account_age = now - member.user.id.created_at().unix_timestamp();
+        if account_age < i64::from(days) * DAY {
+            return Ok(Some(format!(
+                "Your Discord account has to be at least {days} days old (`min_account_age_days`)"
+            )));
+        }
+    }
+
+    if let Some(days) = eligibility.min_membership_days {
+        let member_for = member
+            .joined_at
+            .map(|joined_at| now - joined_at.unix_timestamp())
+            .unwrap_or_default();
+        if member_for < i64::from(days) * DAY {
+            return Ok(Some(format!(
+                "You have to be a member of this server for at least {days} days (`min_membership_days`)"
+            )));
+        }
+    }
+
+    if let Some(roles) = &eligibility.required_roles {
+        if !member.roles.iter().any(|r| roles.contains(&r.get())) {
+            let mentions = roles
+                .iter()
+                .map(|role_id| format!("<@&{role_id}>"))
+                .collect::<Vec<String>>()
+                .join(", ");
+            return Ok(Some(format!(
+                "You need one of these roles: {mentions} (`required_roles`)"
+            )));
+        }
+    }
+
+    if let Some(max) = eligibility.max_pending_requests {
+        let pending = sqlx::query!(
+            "
+            SELECT COUNT(*) as 'count'
+            FROM whitelist_request
+            WHERE discord_id = ? AND status = ? AND id <> ?
+            ",
+            discord_id,
+            WhitelistRequest::PENDING,
+            request_id
+        )
+        .fetch_one(db)
+        .await?
+        .count;
+
+        if pending >= i64::from(max) {
+            return Ok(Some(format!(
+                "You already have {pending} pending requests, which is the limit (`max_pending_requests`)"
+            )));
+        }
+    }
+
+    if let Some(max) = eligibility.max_accounts_per_user {
+        let other_accounts = sqlx::query!(
+            "
+            SELECT COUNT(DISTINCT minecraft_username) as 'count'
+            FROM whitelist_request
+            WHERE discord_id = ? AND status IN (?, ?, ?) AND minecraft_username <> ? AND id <> ?
+            ",
+            discord_id,
+            WhitelistRequest::PENDING,
+            WhitelistRequest::QUEUED,
+            WhitelistRequest::APPROVED,
+            minecraft_username,
+            request_id
+        )
+        .fetch_one(db)
+        .await?
+        .count;
+
+        if other_accounts >= i64::from(max) {
+            return Ok(Some(format!(
+                "You've already requested {other_accounts} other Minecraft accounts, which is the limit (`max_accounts_per_user`)"
+            )));
+        }
+    }
+
+    if let Some(hours) = eligibility.denial_cooldown_hours {
+        let last_denial = sqlx::query!(
+            "
+            SELECT MAX(decided_at) as 'decided_at'
+            FROM whitelist_request
+            WHERE discord_id = ? AND server_id = ? AND status = ?
+            ",
+            discord_id,
+            server.id,
+            WhitelistRequest::DENIED
+        )
+        .fetch_one(db)
+        .await?
+        .decided_at;
+
+        if let Some(last_denial) = last_denial {
+            let retry_at = last_denial.timestamp() + i64::from(hours) * 60 * 60;
+            if now < retry_at {
+                return Ok(Some(format!(
+                    "Your last request for this server was denied, you can request it again <t:{retry_at}:R> (`denial_cooldown_hours`)"
+                )));
+            }
+        }
+    }
+
+    if is_blocked(&eligibility.blocked_players, minecraft_username).await? {
+        return Ok(Some(format!(
+            "The Minecraft account `{minecraft_username}` is blocked (`blocked_players`)"
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Returns whether the account is on the blocklist, by username or by UUID. The UUID is only
+/// looked up if the blocklist contains any.
+pub async fn is_blocked(
+    blocked_players: &[String],
+    minecraft_username: &str,
+) -> Result<bool, Error> {
+    if blocked_players
+        .iter()
+        .any(|blocked| blocked.eq_ignore_ascii_case(minecraft_username))
+    {
+        return Ok(true);
+    }
+
+    let normalize_uuid = |uuid: &str| uuid.replace('-', "").to_ascii_lowercase();
+    let blocked_uuids: Vec<String> = blocked_players
+        .iter()
+        .map(|blocked| normalize_uuid(blocked))
+        .filter(|blocked| blocked.len() == 32 && blocked.chars().all(|c| c.is_ascii_hexdigit()))
+        .collect();
+    if blocked_uuids.is_empty() {
+        return Ok(false);
+    }
+
+    let uuid = utils::lookup_minecraft_uuid(minecraft_username).await?;
+    Ok(uuid.is_some_and(|uuid| blocked_uuids.contains(&normalize_uuid(&uuid))))
+}
+
+/// Whitelists players whose requests were approved while their server wasn't running, once it is.
+pub fn spawn_queue_worker(http: Arc<Http>, config: Arc<ArcSwap<Config>>, db: Pool<MySql>) {
+    tokio::spawn(async move {
+        let containers = ContainerResolver::default();
+        let mut interval = tokio::time::interval(QUEUE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = process_queue(&http, &config.load_full(), &db, &containers).await {
+                error!("Failed to process queued approvals: {err}");
+            }
+        }
+    });
+}
+
+async fn process_queue(
+    http: &Http,
+    config: &Config,
+    db: &Pool<MySql>,
+    containers: &ContainerResolver,
+) -> Result<(), Error> {
+    let queued = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE status = ?",
+        WhitelistRequest::QUEUED
+    )
+    .fetch_all(db)
+    .await?;
+
+    for request in queued {
+        let Some(server) = config.servers.iter().find(|s| s.id == request.server_id) else {
+            continue;
+        };
+
+        match is_running(containers, server).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                warn!("Failed to check whether `{}` is running: {err}", server.id);
+                continue;
+            }
+        }
+
+        if let Err(err) = whitelist_player(config, server, &request.minecraft_username).await {
+            error!("Failed to whitelist queued request `{}`: {err}", request.id);
+            continue;
+        }
+
+        // The request may have been revoked while the player was being whitelisted
+        let approved = set_status(
+            db,
+            &request.id,
+            WhitelistRequest::QUEUED,
+            WhitelistRequest::APPROVED,
+            None,
+            request.reason.as_deref(),
+        )
+        .await?;
+        if !approved {
+            if let Err(err) =
+                unwhitelist_player(config, server, &request.minecraft_username, None).await
+            {
+                error!(
+                    "Failed to remove `{}` from `{}` after request `{}` was revoked: {err}",
+                    request.minecraft_username, server.id, request.id
+                );
+            }
+            continue;
+        }
+        start_access_timer(db, &request.id, server, None).await?;
+        info!(
+            "Whitelisted `{}` on `{}` for queued request `{}`",
+            request.minecraft_username, server.id, request.id
+        );
+
+        let guild_id = request
+            .guild_id
+            .as_deref()
+            .and_then(|id| id.parse::<u64>().ok());
+        actions::run_on_approve(
+            http,
+            server,
+            guild_id,
+            &request.discord_id,
+            &request.minecraft_username,
+        )
+        .await;
+        if !config
+            .whitelist_for(guild_id)
+            .is_some_and(|w| w.send_approval_dm)
+        {
+            continue;
+        }
+
+        let footer = fetch_dm_footer(http, guild_id).await;
+        send_approval_dm(http, &request, server, footer).await;
+    }
+
+    Ok(())
+}
+
+/// Counts the votes of each kind.
+pub fn tally(votes: &[WhitelistVote]) -> (usize, usize) {
+    let approvals = votes
+        .iter()
+        .filter(|v| v.vote == WhitelistVote::APPROVE)
+        .count();
+    (approvals, votes.len() - approvals)
+}
+
+/// Returns the shortest access any approving reviewer chose, so a timed approval is never
+/// turned into a longer one by a later vote.
+pub fn approved_duration(votes: &[WhitelistVote]) -> Option<TimeDelta> {
+    votes
+        .iter()
+        .filter(|v| v.vote == WhitelistVote::APPROVE)
+        .filter_map(|v| v.duration_minutes)
+        .min()
+        .map(TimeDelta::minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::{REPEATED_REQUEST, approved_duration, conflict_reason};
+    use crate::models::database::{WhitelistRequest, WhitelistVote};
+
+    fn request(
+        discord_id: &str,
+        server_id: &str,
+        username: &str,
+        status: &str,
+    ) -> WhitelistRequest {
+        WhitelistRequest {
+            id: "existing".to_string(),
+            server_id: server_id.to_string(),
+            discord_id: discord_id.to_string(),
+            minecraft_username: username.to_string(),
+            created_at: None,
+            guild_id: None,
+            status: status.to_string(),
+            decided_by: None,
+            decided_at: None,
+            reason: None,
+            channel_id: None,
+            message_id: None,
+            expires_at: None,
+            expiry_warned_at: None,
+            revoked_at: None,
+        }
+    }
+
+    fn reason(existing: WhitelistRequest) -> Option<&'static str> {
+        conflict_reason(&existing, "1", "survival", "Steve")
+    }
+
+    #[test]
+    fn detects_repeated_requests() {
+        let pending = request("1", "survival", "steve", WhitelistRequest::PENDING);
+
+        assert_eq!(reason(pending), Some(REPEATED_REQUEST));
+    }
+
+    #[test]
+    fn detects_accounts_already_whitelisted() {
+        let approved = request("1", "survival", "Steve", WhitelistRequest::APPROVED);
+        let queued = request("1", "survival", "Steve", WhitelistRequest::QUEUED);
+
+        assert_eq!(reason(approved), Some("Already whitelisted on this server"));
+        assert_eq!(reason(queued), Some("Already whitelisted on this server"));
+    }
+
+    #[test]
+    fn detects_other_accounts_of_the_member() {
+        let pending = request("1", "survival", "Alex", WhitelistRequest::PENDING);
+        let approved = request("1", "survival", "Alex", WhitelistRequest::APPROVED);
+
+        assert_eq!(reason(pending), Some("Pending request for another account"));
+        assert_eq!(
+            reason(approved),
+            Some("Already whitelisted with another account")
+        );
+    }
+
+    #[test]
+    fn detects_accounts_claimed_by_other_members() {
+        let same_server = request("2", "survival", "Steve", WhitelistRequest::APPROVED);
+        let other_server = request("2", "creative", "STEVE", WhitelistRequest::PENDING);
+
+        assert_eq!(
+            reason(same_server),
+            Some("Account claimed by another member")
+        );
+        assert_eq!(
+            reason(other_server),
+            Some("Account claimed by another member")
+        );
+    }
+
+    #[test]
+    fn ignores_requests_for_other_servers() {
+        let same_account = request("1", "creative", "Steve", WhitelistRequest::APPROVED);
+        let other_account = request("1", "creative", "Alex", WhitelistRequest::PENDING);
+
+        assert_eq!(reason(same_account), None);
+        assert_eq!(reason(other_account), None);
+    }
+
+    fn vote(vote: &str, duration_minutes: Option<i64>) -> WhitelistVote {
+        WhitelistVote {
+            request_id: "existing".to_string(),
+            reviewer_id: "1".to_string(),
+            vote: vote.to_string(),
+            created_at: None,
+            duration_minutes,
+        }
+    }
+
+    #[test]
+    fn applies_the_shortest_approved_duration() {
+        let votes = [
+            vote(WhitelistVote::APPROVE, Some(60 * 24)),
+            vote(WhitelistVote::APPROVE, None),
+            vote(WhitelistVote::DENY, Some(5)),
+            vote(WhitelistVote::APPROVE, Some(90)),
+        ];
+
+        assert_eq!(approved_duration(&votes), Some(TimeDelta::minutes(90)));
+        assert_eq!(approved_duration(&votes[1..2]), None);
+    }
+}
@@ -0,0 +1,613 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use poise::serenity_prelude::{
+    self as serenity, CacheHttp, ChannelId, CreateEmbed, CreateMessage, GuildId, GuildPagination,
+    Http, RoleId, UserId,
+};
+use sqlx::{MySql, Pool};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    Error, actions,
+    models::{
+        config::{Config, Server},
+        database::WhitelistRequest,
+    },
+    requests,
+};
+
+/// How often the whitelists of servers with a `whitelist_role` are reconciled in full.
+const SYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Members fetched per page, the most Discord allows.
+const MEMBERS_PER_PAGE: u64 = 1000;
+
+/// Most entries listed per section of a sync report, keeping fields within Discord's limits.
+const REPORT_ENTRIES_SHOWN: usize = 15;
+
+/// Changes that bring a server's whitelist in line with who has its `whitelist_role`.
+#[derive(Debug, Default)]
+pub struct SyncDiff {
+    /// Members with the role and the accounts to whitelist for them
+    pub add: Vec<(String, String)>,
+    /// Requests of members who don't have the role anymore
+    pub remove: Vec<WhitelistRequest>,
+    /// Whitelisted players breeze doesn't know about, which are left alone
+    pub unmanaged: Vec<String>,
+}
+
+impl SyncDiff {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+
+    /// Creates the report of the diff, saying whether it was applied or only previewed.
+    pub fn to_embed(&self, server: &Server, role_id: u64, applied: bool) -> CreateEmbed {
+        fn list(lines: Vec<String>) -> String {
+            let mut shown: Vec<String> = lines.iter().take(REPORT_ENTRIES_SHOWN).cloned().collect();
+            if lines.len() > REPORT_ENTRIES_SHOWN {
+                shown.push(format!("_{} more_", lines.len() - REPORT_ENTRIES_SHOWN));
+            }
+            shown.join("\n")
+        }
+
+        let title = match applied {
+            true => format!("🔄 Synced whitelist of {}", server.name),
+            false => format!("🔍 Whitelist sync preview for {}", server.name),
+        };
+        let description = match (self.is_empty(), applied) {
+            (true, _) => format!("Everyone with <@&{role_id}> is whitelisted, nothing to change."),
+            (false, true) => format!("Brought the whitelist in line with who has <@&{role_id}>."),
+            (false, false) => format!(
+                "These changes would bring the whitelist in line with who has <@&{role_id}>. Nothing was changed."
+            ),
+        };
+
+        let mut embed = CreateEmbed::new()
+            .title(title)
+            .color(0x04a5e5)
+            .description(description);
+        if !self.add.is_empty() {
+            let lines = self
+                .add
+                .iter()
+                .map(|(discord_id, username)| format!("`{username}` for <@{discord_id}>"))
+                .collect();
+            embed = embed.field("➕ Whitelist", list(lines), false);
+        }
+        if !self.remove.is_empty() {
+            let lines = self
+                .remove
+                .iter()
+                .map(|r| format!("`{}` of <@{}>", r.minecraft_username, r.discord_id))
+                .collect();
+            embed = embed.field("➖ Remove", list(lines), false);
+        }
+        if !self.unmanaged.is_empty() {
+            let lines = self.unmanaged.iter().map(|u| format!("`{u}`")).collect();
+            embed = embed.field("❔ Not requested through breeze", list(lines), false);
+        }
+
+        embed
+    }
+
+    /// Identifies the changes of the diff, regardless of their order.
+    fn signature(&self) -> String {
+        let mut entries: Vec<String> = self
+            .add
+            .iter()
+            .map(|(discord_id, username)| format!("+{discord_id}:{username}"))
+            .chain(self.remove.iter().map(|r| format!("-{}", r.id)))
+            .chain(self.unmanaged.iter().map(|u| format!("?{u}")))
+            .collect();
+        entries.sort();
+        entries.join(",")
+    }
+}
+
+/// Remembers which whitelist roles members were last seen with and which dry-run reports were
+/// last posted, so member updates that can't have changed a whitelist role and unchanged
+/// previews are skipped.
+#[derive(Debug, Default)]
+pub struct RoleSyncState {
+    seen_roles: Mutex<HashMap<(GuildId, UserId), Vec<RoleId>>>,
+    last_reports: Mutex<HashMap<String, String>>,
+}
+
+impl RoleSyncState {
+    /// Returns whether a member update changed which whitelist roles of the guild's servers the
+    /// member has. When the previous roles aren't cached, they're compared with the roles the
+    /// member was last seen with instead, so only the first such update syncs.
+    pub async fn touches_whitelist_role(
+        &self,
+        config: &Config,
+        guild_id: GuildId,
+        user_id: UserId,
+        old_roles: Option<&[RoleId]>,
+        roles: &[RoleId],
+    ) -> bool {
+        let whitelist_roles: HashSet<RoleId> = config
+            .servers_for(Some(guild_id.get()))
+            .iter()
+            .filter_map(|s| s.whitelist_role())
+            .map(RoleId::new)
+            .collect();
+        if whitelist_roles.is_empty() {
+            return false;
+        }
+
+        let held = |roles: &[RoleId]| {
+            let mut held: Vec<RoleId> = roles
+                .iter()
+                .filter(|r| whitelist_roles.contains(r))
+                .copied()
+                .collect();
+            held.sort();
+            held
+        };
+        let now = held(roles);
+        let last_seen = self
+            .seen_roles
+            .lock()
+            .await
+            .insert((guild_id, user_id), now.clone());
+
+        match old_roles {
+            Some(old_roles) => held(old_roles) != now,
+            None => last_seen.is_none_or(|last_seen| last_seen != now),
+        }
+    }
+
+    /// Returns whether a dry-run report of the diff should be posted for the server, which is
+    /// only the case when it differs from the last one posted.
+    async fn should_report(&self, server: &Server, diff: &SyncDiff) -> bool {
+        let signature = diff.signature();
+        let mut last_reports = self.last_reports.lock().await;
+        if last_reports.get(&server.id) == Some(&signature) {
+            return false;
+        }
+        last_reports.insert(server.id.clone(), signature);
+        true
+    }
+
+    /// Forgets the last dry-run report of the server once there's nothing left to report.
+    async fn clear_report(&self, server: &Server) {
+        self.last_reports.lock().await.remove(&server.id);
+    }
+}
+
+/// Returns the Minecraft account each member requested most recently through the guild, among
+/// requests that were approved at some point.
+async fn linked_accounts(
+    config: &Config,
+    db: &Pool<MySql>,
+    guild_id: u64,
+) -> Result<HashMap<String, String>, Error> {
+    let requests = sqlx::query_as!(
+        WhitelistRequest,
+        "
+        SELECT *
+        FROM whitelist_request
+        WHERE status IN (?, ?, ?)
+        ORDER BY decided_at
+        ",
+        WhitelistRequest::APPROVED,
+        WhitelistRequest::QUEUED,
+        WhitelistRequest::REVOKED
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(requests
+        .into_iter()
+        .filter(|r| requests::made_in_guild(config, r, guild_id))
+        .map(|r| (r.discord_id, r.minecraft_username))
+        .collect())
+}
+
+/// Returns the approved and queued requests for the server made through the guild.
+async fn server_access(
+    config: &Config,
+    db: &Pool<MySql>,
+    server: &Server,
+    guild_id: u64,
+) -> Result<Vec<WhitelistRequest>, Error> {
+    let requests = sqlx::query_as!(
+        WhitelistRequest,
+        "SELECT * FROM whitelist_request WHERE server_id = ? AND status IN (?, ?)",
+        server.id,
+        WhitelistRequest::APPROVED,
+        WhitelistRequest::QUEUED
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(requests
+        .into_iter()
+        .filter(|r| requests::made_in_guild(config, r, guild_id))
+        .collect())
+}
+
+/// Compares who has the server's whitelist role in the guild with its live whitelist.
+pub async fn compute_diff(
+    http: &Http,
+    config: &Config,
+    db: &Pool<MySql>,
+    server: &Server,
+    guild_id: GuildId,
+) -> Result<SyncDiff, Error> {
+    let role_id = RoleId::new(server.whitelist_role().ok_or("No whitelist role set")?);
+
+    let mut holders = HashSet::new();
+    let mut after: Option<UserId> = None;
+    loop {
+        let members = guild_id
+            .members(http, Some(MEMBERS_PER_PAGE), after)
+            .await?;
+        holders.extend(
+            members
+                .iter()
+                .filter(|m| m.roles.contains(&role_id))
+                .map(|m| m.user.id.to_string()),
+        );
+        after = members.last().map(|m| m.user.id);
+        if (members.len() as u64) < MEMBERS_PER_PAGE {
+            break;
+        }
+    }
+
+    let live: HashSet<String> = requests::list_whitelist(config, server)
+        .await?
+        .into_iter()
+        .map(|player| player.to_lowercase())
+        .collect();
+    let access = server_access(config, db, server, guild_id.get()).await?;
+    let linked = linked_accounts(config, db, guild_id.get()).await?;
+
+    let mut diff = SyncDiff::default();
+    let mut known: HashSet<String> = HashSet::new();
+    for holder in &holders {
+        let accounts: Vec<&WhitelistRequest> =
+            access.iter().filter(|r| &r.discord_id == holder).collect();
+        match accounts.is_empty() {
+            true => {
+                if let Some(username) = linked.get(holder) {
+                    known.insert(username.to_lowercase());
+                    diff.add.push((holder.clone(), username.clone()));
+                }
+            }
+            // Approved players who went missing from the whitelist are added back
+            false => {
+                for request in accounts {
+                    if request.status == WhitelistRequest::APPROVED
+                        && !live.contains(&request.minecraft_username.to_lowercase())
+                    {
+                        diff.add
+                            .push((holder.clone(), request.minecraft_username.clone()));
+                    }
+                }
+            }
+        }
+    }
+    for request in access {
+        known.insert(request.minecraft_username.to_lowercase());
+        if !holders.contains(&request.discord_id) {
+            diff.remove.push(request);
+        }
+    }
+    diff.unmanaged = live.into_iter().filter(|p| !known.contains(p)).collect();
+    diff.unmanaged.sort();
+
+    Ok(diff)
+}
+
+/// Whitelists and removes the players in the diff, recording each change as a request.
+pub async fn apply_diff(
+    http: &Http,
+    config: &Config,
+    db: &Pool<MySql>,
+    server: &Server,
+    guild_id: GuildId,
+    diff: &SyncDiff,
+) -> Result<(), Error> {
+    let guild_id_s = guild_id.to_string();
+
+    for (discord_id, username) in &diff.add {
+        requests::whitelist_player(config, server, username).await?;
+
+        let already_approved = sqlx::query!(
+            "
+            SELECT EXISTS(
+                SELECT 1 FROM whitelist_request
+                WHERE discord_id = ? AND server_id = ? AND minecraft_username = ? AND status = ?
+            ) as 'exists'
+            ",
+            discord_id,
+            server.id,
+            username,
+            WhitelistRequest::APPROVED
+        )
+        .fetch_one(db)
+        .await?
+        .exists
+            > 0;
+        if already_approved {
+            continue;
+        }
+
+        let request_id = Uuid::new_v4().to_string();
+        sqlx::query!(
+            "
+            INSERT INTO whitelist_request
+                (id, server_id, discord_id, minecraft_username, guild_id, status, decided_at, reason)
+            VALUES ( ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, ? )
+            ",
+            request_id,
+            server.id,
+            discord_id,
+            username,
+            guild_id_s,
+            WhitelistRequest::APPROVED,
+            "Has the whitelist role"
+        )
+        .execute(db)
+        .await?;
+        info!(
+            "Whitelisted `{username}` on `{}` since they have the whitelist role",
+            server.id
+        );
+        actions::run_on_approve(http, server, Some(guild_id.get()), discord_id, username).await;
+    }
+
+    for request in &diff.remove {
+        // Queued players haven't been whitelisted yet
+        if request.status == WhitelistRequest::APPROVED {
+            requests::unwhitelist_player(config, server, &request.minecraft_username, None).await?;
+        }
+        requests::revoke(db, &request.id, "Lost the whitelist role").await?;
+        info!(
+            "Removed `{}` from `{}` since they lost the whitelist role",
+            request.minecraft_username, server.id
+        );
+        actions::undo_on_approve(
+            http,
+            db,
+            server,
+            Some(guild_id.get()),
+            &request.discord_id,
+            &request.minecraft_username,
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+async fn report(
+    http: &Http,
+    config: &Config,
+    server: &Server,
+    guild_id: GuildId,
+    embed: CreateEmbed,
+) {
+    let Some(whitelist) = config.whitelist_for(Some(guild_id.get())) else {
+        return;
+    };
+
+    let channel = whitelist
+        .log_channel
+        .unwrap_or_else(|| server.request_channel(whitelist));
+    if let Err(err) = ChannelId::new(channel)
+        .send_message(http, CreateMessage::new().add_embed(embed))
+        .await
+    {
+        warn!(
+            "Failed to report the whitelist sync of `{}`: {err}",
+            server.id
+        );
+    }
+}
+
+/// Applies role changes of a single member to the whitelists of the guild's servers that follow
+/// a role, or only reports them for servers in dry-run mode.
+pub async fn sync_member(
+    ctx: &serenity::Context,
+    config: &Config,
+    db: &Pool<MySql>,
+    state: &RoleSyncState,
+    guild_id: GuildId,
+    user_id: UserId,
+    roles: &[RoleId],
+) -> Result<(), Error> {
+    let discord_id = user_id.to_string();
+
+    for server in config.servers_for(Some(guild_id.get())) {
+        let Some(role_id) = server.whitelist_role() else {
+            continue;
+        };
+        // Roles are only meaningful in the guild they belong to
+        let role_in_guild = ctx
+            .cache
+            .guild(guild_id)
+            .is_some_and(|g| g.roles.contains_key(&RoleId::new(role_id)));
+        if !role_in_guild {
+            continue;
+        }
+
+        let has_role = roles.contains(&RoleId::new(role_id));
+        let access: Vec<WhitelistRequest> = server_access(config, db, server, guild_id.get())
+            .await?
+            .into_iter()
+            .filter(|r| r.discord_id == discord_id)
+            .collect();
+
+        let mut diff = SyncDiff::default();
+        match (has_role, access.is_empty()) {
+            (true, true) => {
+                let linked = linked_accounts(config, db, guild_id.get()).await?;
+                if let Some(username) = linked.get(&discord_id) {
+                    diff.add.push((discord_id.clone(), username.clone()));
+                }
+            }
+            (false, false) => diff.remove = access,
+            _ => {}
+        }
+        if diff.is_empty() {
+            continue;
+        }
+
+        let dry_run = server.role_sync_dry_run();
+        if dry_run && !state.should_report(server, &diff).await {
+            continue;
+        }
+        if !dry_run {
+            if let Err(err) = apply_diff(ctx.http(), config, db, server, guild_id, &diff).await {
+                error!(
+                    "Failed to sync the whitelist role of `{}`: {err}",
+                    server.id
+                );
+                continue;
+            }
+        }
+        report(
+            ctx.http(),
+            config,
+            server,
+            guild_id,
+            diff.to_embed(server, role_id, !dry_run),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Lists every guild the bot is in, which Discord returns in pages of up to 200.
+async fn all_guilds(http: &Http) -> Result<Vec<GuildId>, Error> {
+    const PAGE_SIZE: u64 = 200;
+
+    let mut guilds: Vec<GuildId> = vec![];
+    loop {
+        let after = guilds.last().map(|id| GuildPagination::After(*id));
+        let page = http.get_guilds(after, Some(PAGE_SIZE)).await?;
+        let full = page.len() as u64 == PAGE_SIZE;
+        guilds.extend(page.into_iter().map(|g| g.id));
+
+        if !full {
+            return Ok(guilds);
+        }
+    }
+}
+
+/// Finds the guild a role belongs to, among the guilds the server is visible in.
+async fn find_role_guild(
+    http: &Http,
+    config: &Config,
+    server: &Server,
+    role_id: u64,
+) -> Result<Option<GuildId>, Error> {
+    let candidates: Vec<GuildId> = match config.guilds.is_empty() {
+        true => all_guilds(http).await?,
+        false => config
+            .guilds
+            .iter()
+            .filter(|g| {
+                config
+                    .servers_for(Some(g.id))
+                    .iter()
+                    .any(|s| s.id == server.id)
+            })
+            .map(|g| GuildId::new(g.id))
+            .collect(),
+    };
+
+    for guild_id in candidates {
+        if guild_id
+            .roles(http)
+            .await?
+            .contains_key(&RoleId::new(role_id))
+        {
+            return Ok(Some(guild_id));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Periodically reconciles the whitelists of servers with a `whitelist_role` against their live
+/// whitelists, in case role changes were missed while the bot was offline.
+pub fn spawn_role_sync(
+    http: Arc<Http>,
+    config: Arc<ArcSwap<Config>>,
+    db: Pool<MySql>,
+    state: Arc<RoleSyncState>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            let config = config.load_full();
+
+            for server in &config.servers {
+                let Some(role_id) = server.whitelist_role() else {
+                    continue;
+                };
+                if let Err(err) = sync_server(&http, &config, &db, &state, server, role_id).await {
+                    error!(
+                        "Failed to sync the whitelist role of `{}`: {err}",
+                        server.id
+                    );
+                }
+            }
+        }
+    });
+}
+
+async fn sync_server(
+    http: &Http,
+    config: &Config,
+    db: &Pool<MySql>,
+    state: &RoleSyncState,
+    server: &Server,
+    role_id: u64,
+) -> Result<(), Error> {
+    let Some(guild_id) = find_role_guild(http, config, server, role_id).await? else {
+        warn!(
+            "The whitelist role of `{}` wasn't found in any guild",
+            server.id
+        );
+        return Ok(());
+    };
+
+    let diff = compute_diff(http, config, db, server, guild_id).await?;
+    if diff.is_empty() {
+        state.clear_report(server).await;
+        return Ok(());
+    }
+
+    let dry_run = server.role_sync_dry_run();
+    if dry_run && !state.should_report(server, &diff).await {
+        return Ok(());
+    }
+    if !dry_run {
+        apply_diff(http, config, db, server, guild_id, &diff).await?;
+    }
+    report(
+        http,
+        config,
+        server,
+        guild_id,
+        diff.to_embed(server, role_id, !dry_run),
+    )
+    .await;
+
+    Ok(())
+}
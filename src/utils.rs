@@ -1,6 +1,11 @@
 use crate::Context;
+use chrono::TimeDelta;
 use mc_query::rcon::RconClient;
+use md5::{Digest, Md5};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
 use poise::serenity_prelude::AutocompleteChoice;
+use serde::Deserialize;
+use uuid::Uuid;
 
 /// Returns true if the given Minecraft username is valid.
 pub async fn validate_minecraft_username(username: &str) -> Result<bool, crate::Error> {
@@ -15,6 +20,94 @@ pub async fn validate_minecraft_username(username: &str) -> Result<bool, crate::
     }
 }
 
+#[derive(Deserialize)]
+struct MinecraftProfile {
+    id: String,
+}
+
+/// Derives the UUID an offline-mode server gives a player, the same way vanilla does.
+pub fn offline_uuid(username: &str) -> Uuid {
+    let hash = Md5::digest(format!("OfflinePlayer:{username}"));
+    uuid::Builder::from_md5_bytes(hash.into()).into_uuid()
+}
+
+/// Looks up the UUID of a Minecraft account, returning `None` if there's no such account.
+pub async fn lookup_minecraft_uuid(username: &str) -> Result<Option<String>, crate::Error> {
+    let res = reqwest::get(&format!(
+        "https://api.minecraftservices.com/minecraft/profile/lookup/name/{}",
+        username
+    ))
+    .await?;
+
+    if !res.status().is_success() {
+        return Ok(None);
+    }
+
+    let profile: MinecraftProfile = serde_json::from_str(&res.text().await?)?;
+    Ok(Some(profile.id))
+}
+
+#[derive(Deserialize)]
+struct XboxProfile {
+    xuid: Option<serde_json::Value>,
+}
+
+/// Looks up the XUID of a Bedrock player through the given endpoint, with `{gamertag}` in place
+/// of the percent-encoded gamertag, returning `None` if there's no such player.
+pub async fn lookup_bedrock_xuid(
+    lookup_url: &str,
+    gamertag: &str,
+) -> Result<Option<String>, crate::Error> {
+    let gamertag = utf8_percent_encode(gamertag, NON_ALPHANUMERIC).to_string();
+    let res = reqwest::get(&lookup_url.replace("{gamertag}", &gamertag)).await?;
+
+    if !res.status().is_success() {
+        return Ok(None);
+    }
+
+    // e.g. {"xuid": 2535428571842417}, or {} if there's no such player
+    let profile: XboxProfile = serde_json::from_str(&res.text().await?)?;
+    Ok(profile
+        .xuid
+        .filter(|xuid| !xuid.is_null())
+        .map(|xuid| xuid.to_string().trim_matches('"').to_string()))
+}
+
+/// Parses a duration made of minutes, hours, days and weeks, like `90m`, `48h` or `1w2d`.
+/// Returns `None` for anything else, including durations of zero.
+pub fn parse_duration(input: &str) -> Option<TimeDelta> {
+    let mut total = TimeDelta::zero();
+    let mut digits = String::new();
+
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let value: i64 = digits.parse().ok()?;
+        digits.clear();
+        let part = match c.to_ascii_lowercase() {
+            'm' => TimeDelta::try_minutes(value)?,
+            'h' => TimeDelta::try_hours(value)?,
+            'd' => TimeDelta::try_days(value)?,
+            'w' => TimeDelta::try_weeks(value)?,
+            _ => return None,
+        };
+        total = total.checked_add(&part)?;
+    }
+
+    (digits.is_empty() && total > TimeDelta::zero()).then_some(total)
+}
+
+/// Shortens the text to at most `max` characters, marking where it was cut off.
+pub fn truncate(text: &str, max: usize) -> String {
+    match text.chars().count() > max {
+        true => format!("{}…", text.chars().take(max - 1).collect::<String>()),
+        false => text.to_string(),
+    }
+}
+
 /// Creates a new [RconClient] and authenticates with the given password.
 ///
 /// [RconClient](mc_query::rcon::RconClient)
@@ -28,19 +121,100 @@ pub async fn create_rcon_client(
     Ok(client)
 }
 
+/// Autocompletes the servers and groups of servers whitelisting can be requested for.
+pub async fn autocomplete_request_targets(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    let config = ctx.data().config.load();
+    let guild_id = ctx.guild_id().map(|id| id.get());
+
+    let groups = config
+        .groups
+        .iter()
+        .filter(|g| config.request_target(guild_id, &g.id).is_some())
+        .map(|g| (format!("{} (group: {})", g.name, g.id), g.id.clone()));
+    let servers = config
+        .servers_for(guild_id)
+        .into_iter()
+        .map(|s| (format!("{} ({})", s.name, s.id), s.id.clone()));
+
+    groups
+        .chain(servers)
+        .filter(|(_, id)| id.starts_with(partial))
+        .map(|(name, id)| AutocompleteChoice::new(name, id))
+        .collect::<Vec<AutocompleteChoice>>()
+        .into_iter()
+}
+
 /// Autocompletes server IDs in commands based on the servers in the config.
 pub async fn autocomplete_server_ids(
     ctx: Context<'_>,
     partial: &str,
 ) -> impl Iterator<Item = AutocompleteChoice> {
-    ctx.data().config.servers.iter().filter_map(move |s| {
-        if s.id.starts_with(partial) {
-            Some(AutocompleteChoice::new(
-                format!("{} ({})", s.name, s.id),
-                s.id.clone(),
-            ))
-        } else {
-            None
-        }
-    })
+    let config = ctx.data().config.load();
+    config
+        .servers_for(ctx.guild_id().map(|id| id.get()))
+        .into_iter()
+        .filter_map(move |s| {
+            if s.id.starts_with(partial) {
+                Some(AutocompleteChoice::new(
+                    format!("{} ({})", s.name, s.id),
+                    s.id.clone(),
+                ))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<AutocompleteChoice>>()
+        .into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::{offline_uuid, parse_duration};
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90m"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration(" 48H "), Some(TimeDelta::hours(48)));
+        assert_eq!(parse_duration("1w2d"), Some(TimeDelta::days(9)));
+        assert_eq!(
+            parse_duration("1d12h30m"),
+            Some(TimeDelta::minutes(36 * 60 + 30))
+        );
+    }
+
+    #[test]
+    fn rejects_empty_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("0w0d"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        assert_eq!(parse_duration("5"), None);
+        assert_eq!(parse_duration("1d5"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("3y"), None);
+        assert_eq!(parse_duration("-3d"), None);
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert_eq!(parse_duration("99999999999999999999m"), None);
+        assert_eq!(parse_duration("9999999999999w"), None);
+        assert_eq!(parse_duration("10000000000w10000000000w"), None);
+    }
+
+    #[test]
+    fn derives_offline_uuids() {
+        assert_eq!(
+            offline_uuid("Notch").to_string(),
+            "b50ad385-829d-3141-a216-7e7d7539ba7f"
+        );
+    }
 }
@@ -0,0 +1,848 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+use regex::Regex;
+
+use crate::{
+    models::config::{
+        AutoApproveRule, Config, FormQuestion, GroupWhitelistTarget, Server, UsernameValidation,
+        WhitelistConfig,
+    },
+    permissions::{self, Permission},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in the config, along with the path of the field it applies to.
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn error(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Checks the config for mistakes that deserializing alone doesn't catch, returning every problem
+/// found rather than stopping at the first one.
+pub fn validate(config: &Config) -> Vec<ConfigIssue> {
+    let mut issues = vec![];
+
+    match &config.whitelist {
+        Some(whitelist) => validate_whitelist("whitelist", whitelist, &mut issues),
+        None if config.guilds.is_empty() => issues.push(ConfigIssue::error(
+            "whitelist",
+            "missing `whitelist` section",
+        )),
+        None => {}
+    }
+
+    let mut seen_guilds: HashMap<u64, usize> = HashMap::new();
+    for (i, guild) in config.guilds.iter().enumerate() {
+        let path = format!("guilds[{i}]");
+
+        if guild.id == 0 {
+            issues.push(ConfigIssue::error(
+                format!("{path}.id"),
+                "must be a guild ID",
+            ));
+        } else if let Some(first) = seen_guilds.insert(guild.id, i) {
+            issues.push(ConfigIssue::error(
+                format!("{path}.id"),
+                format!(
+                    "duplicate guild ID `{}` (already used by `guilds[{first}]`)",
+                    guild.id
+                ),
+            ));
+        }
+
+        match &guild.whitelist {
+            Some(whitelist) => {
+                validate_whitelist(&format!("{path}.whitelist"), whitelist, &mut issues)
+            }
+            None if config.whitelist.is_none() => issues.push(ConfigIssue::error(
+                format!("{path}.whitelist"),
+                "missing `whitelist` section, and there's no top-level one to fall back to",
+            )),
+            None => {}
+        }
+
+        for (j, server_id) in guild.servers.iter().flatten().enumerate() {
+            if !config.servers.iter().any(|s| &s.id == server_id) {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.servers[{j}]"),
+                    format!("unknown server ID `{server_id}`"),
+                ));
+            }
+        }
+    }
+
+    for (i, guild_id) in config.commands.guilds.iter().enumerate() {
+        if *guild_id == 0 {
+            issues.push(ConfigIssue::error(
+                format!("commands.guilds[{i}]"),
+                "must be a guild ID",
+            ));
+        }
+    }
+    if config.commands.dev_guild == Some(0) {
+        issues.push(ConfigIssue::error(
+            "commands.dev_guild",
+            "must be a guild ID",
+        ));
+    }
+    if !config.commands.register_globally && config.commands.guilds.is_empty() {
+        issues.push(ConfigIssue::warning(
+            "commands",
+            "`register_globally` is disabled and no `guilds` are set, so commands are only registered in dev mode",
+        ));
+    }
+
+    validate_permissions("permissions", &config.permissions, &mut issues);
+
+    if config.servers.is_empty() {
+        issues.push(ConfigIssue::warning("servers", "no servers are configured"));
+    }
+
+    let mut seen_ids: HashMap<&str, usize> = HashMap::new();
+    for (i, server) in config.servers.iter().enumerate() {
+        let path = format!("servers[{i}]");
+
+        if server.id.is_empty() {
+            issues.push(ConfigIssue::error(
+                format!("{path}.id"),
+                "must not be empty",
+            ));
+        } else if let Some(first) = seen_ids.insert(&server.id, i) {
+            issues.push(ConfigIssue::error(
+                format!("{path}.id"),
+                format!(
+                    "duplicate server ID `{}` (already used by `servers[{first}]`)",
+                    server.id
+                ),
+            ));
+        }
+        if server.name.is_empty() {
+            issues.push(ConfigIssue::error(
+                format!("{path}.name"),
+                "must not be empty",
+            ));
+        }
+        if server.address.is_empty() {
+            issues.push(ConfigIssue::error(
+                format!("{path}.address"),
+                "must not be empty",
+            ));
+        }
+        if server.rcon_port == 0 {
+            issues.push(ConfigIssue::error(
+                format!("{path}.rcon_port"),
+                "must be a valid port",
+            ));
+        }
+        if server.query_port == 0 {
+            issues.push(ConfigIssue::error(
+                format!("{path}.query_port"),
+                "must be a valid port",
+            ));
+        }
+        if server.rcon_password.is_empty() && server.rcon_password_file.is_none() {
+            issues.push(ConfigIssue::error(
+                format!("{path}.rcon_password"),
+                "missing RCON password, requests for this server can't be approved",
+            ));
+        }
+
+        match (&server.compose_project, &server.compose_service) {
+            (Some(_), None) => issues.push(ConfigIssue::error(
+                format!("{path}.compose_service"),
+                "must be set when `compose_project` is set",
+            )),
+            (None, Some(_)) => issues.push(ConfigIssue::error(
+                format!("{path}.compose_project"),
+                "must be set when `compose_service` is set",
+            )),
+            _ => {}
+        }
+        if let Some(whitelist) = &server.whitelist {
+            if whitelist.request_channel == Some(0) {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.whitelist.request_channel"),
+                    "must be a channel ID",
+                ));
+            }
+            if whitelist.whitelist_role == Some(0) {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.whitelist.whitelist_role"),
+                    "must be a role ID",
+                ));
+            }
+            let thresholds = [
+                ("required_approvals", whitelist.required_approvals),
+                ("required_denials", whitelist.required_denials),
+                ("request_ttl_hours", whitelist.request_ttl_hours),
+                ("access_hours", whitelist.access_hours),
+            ];
+            for (field, threshold) in thresholds {
+                if threshold == Some(0) {
+                    issues.push(ConfigIssue::error(
+                        format!("{path}.whitelist.{field}"),
+                        "must be at least 1",
+                    ));
+                }
+            }
+            let role_lists = [
+                ("ping_roles", &whitelist.ping_roles),
+                ("approver_roles", &whitelist.approver_roles),
+            ];
+            for (field, role_ids) in role_lists {
+                for (j, role_id) in role_ids.iter().flatten().enumerate() {
+                    if *role_id == 0 {
+                        issues.push(ConfigIssue::error(
+                            format!("{path}.whitelist.{field}[{j}]"),
+                            "must be a role ID",
+                        ));
+                    }
+                }
+            }
+            if let Some(rules) = &whitelist.auto_approve {
+                validate_auto_approve(
+                    &format!("{path}.whitelist.auto_approve"),
+                    rules,
+                    Some(server),
+                    &mut issues,
+                );
+            }
+            if let Some(pattern) = &whitelist.username_pattern {
+                if let Err(err) = Regex::new(pattern) {
+                    issues.push(ConfigIssue::error(
+                        format!("{path}.whitelist.username_pattern"),
+                        format!("invalid pattern: {err}"),
+                    ));
+                }
+                if whitelist.username_validation != Some(UsernameValidation::Regex) {
+                    issues.push(ConfigIssue::warning(
+                        format!("{path}.whitelist.username_pattern"),
+                        "is only used with `username_validation = \"regex\"`",
+                    ));
+                }
+            }
+            for (field, command) in [
+                ("whitelist_add", &whitelist.whitelist_add),
+                ("whitelist_remove", &whitelist.whitelist_remove),
+            ] {
+                if command
+                    .as_ref()
+                    .is_some_and(|c| !c.contains("{username}") && !c.contains("{uuid}"))
+                {
+                    issues.push(ConfigIssue::error(
+                        format!("{path}.whitelist.{field}"),
+                        "must contain `{username}` or `{uuid}`",
+                    ));
+                }
+            }
+            if whitelist
+                .kick
+                .as_ref()
+                .is_some_and(|c| !c.contains("{username}"))
+            {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.whitelist.kick"),
+                    "must contain `{username}`",
+                ));
+            }
+            if let Some(bedrock) = &whitelist.bedrock {
+                let path = format!("{path}.whitelist.bedrock");
+                if bedrock.prefix.is_empty()
+                    || bedrock
+                        .prefix
+                        .chars()
+                        .any(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    issues.push(ConfigIssue::error(
+                        format!("{path}.prefix"),
+                        "must be set and can't contain letters, digits or underscores, since Bedrock players are told apart from Java players by it",
+                    ));
+                }
+                if !bedrock.xuid_lookup_url.contains("{gamertag}") {
+                    issues.push(ConfigIssue::error(
+                        format!("{path}.xuid_lookup_url"),
+                        "must contain `{gamertag}`",
+                    ));
+                }
+                for (field, command) in [
+                    ("whitelist_add", &bedrock.whitelist_add),
+                    ("whitelist_remove", &bedrock.whitelist_remove),
+                ] {
+                    if !command.contains("{gamertag}") && !command.contains("{username}") {
+                        issues.push(ConfigIssue::error(
+                            format!("{path}.{field}"),
+                            "must contain `{gamertag}` or `{username}`",
+                        ));
+                    }
+                }
+            }
+            if let Some(on_approve) = &whitelist.on_approve {
+                for (j, role_id) in on_approve.roles.iter().enumerate() {
+                    if *role_id == 0 {
+                        issues.push(ConfigIssue::error(
+                            format!("{path}.whitelist.on_approve.roles[{j}]"),
+                            "must be a role ID",
+                        ));
+                    }
+                }
+                if on_approve.welcome_channel == Some(0) {
+                    issues.push(ConfigIssue::error(
+                        format!("{path}.whitelist.on_approve.welcome_channel"),
+                        "must be a channel ID",
+                    ));
+                }
+                if on_approve.welcome_message.is_some() && on_approve.welcome_channel.is_none() {
+                    issues.push(ConfigIssue::warning(
+                        format!("{path}.whitelist.on_approve.welcome_message"),
+                        "is never sent since `welcome_channel` isn't set",
+                    ));
+                }
+            }
+            validate_application_form(
+                &format!("{path}.whitelist.application_form"),
+                &whitelist.application_form,
+                &mut issues,
+            );
+        }
+        validate_permissions(
+            &format!("{path}.permissions"),
+            &server.permissions,
+            &mut issues,
+        );
+        for permission in server.permissions.keys().filter(|p| p.is_global()) {
+            issues.push(ConfigIssue::warning(
+                format!("{path}.permissions.\"{permission}\""),
+                "applies to the whole bot, so it can only be granted in the top-level `permissions`",
+            ));
+        }
+        if server.container_ref().is_none() {
+            issues.push(ConfigIssue::error(
+                path.clone(),
+                "no container configured, set `container_id`, `container_name` or `compose_project` and `compose_service`",
+            ));
+        }
+    }
+
+    validate_reviewers(config, &mut issues);
+
+    let mut seen_groups: HashMap<&str, usize> = HashMap::new();
+    for (i, group) in config.groups.iter().enumerate() {
+        let path = format!("groups[{i}]");
+
+        if group.id.is_empty() {
+            issues.push(ConfigIssue::error(
+                format!("{path}.id"),
+                "must not be empty",
+            ));
+        } else if let Some(first) = seen_groups.insert(&group.id, i) {
+            issues.push(ConfigIssue::error(
+                format!("{path}.id"),
+                format!(
+                    "duplicate group ID `{}` (already used by `groups[{first}]`)",
+                    group.id
+                ),
+            ));
+        } else if seen_ids.contains_key(group.id.as_str()) {
+            issues.push(ConfigIssue::error(
+                format!("{path}.id"),
+                format!("`{}` is already the ID of a server", group.id),
+            ));
+        }
+        if group.name.is_empty() {
+            issues.push(ConfigIssue::error(
+                format!("{path}.name"),
+                "must not be empty",
+            ));
+        }
+
+        match &group.proxy {
+            Some(proxy) if !seen_ids.contains_key(proxy.as_str()) => {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.proxy"),
+                    format!("unknown server ID `{proxy}`"),
+                ))
+            }
+            Some(_) => {}
+            None if group.whitelist_on == GroupWhitelistTarget::Proxy => {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.whitelist_on"),
+                    "is `proxy`, but the group has no `proxy`",
+                ))
+            }
+            None => {}
+        }
+        if config.group_members(&group.id).is_empty() {
+            issues.push(ConfigIssue::warning(
+                path.clone(),
+                format!(
+                    "no servers are in this group, set `group = \"{}\"` on its servers",
+                    group.id
+                ),
+            ));
+        }
+    }
+
+    issues
+}
+
+fn validate_application_form(path: &str, form: &[FormQuestion], issues: &mut Vec<ConfigIssue>) {
+    if form.len() > FormQuestion::MAX_QUESTIONS {
+        issues.push(ConfigIssue::error(
+            path,
+            format!(
+                "at most {} questions are allowed",
+                FormQuestion::MAX_QUESTIONS
+            ),
+        ));
+    }
+
+    for (i, question) in form.iter().enumerate() {
+        let path = format!("{path}[{i}]");
+        let label_length = question.label.chars().count();
+        if label_length == 0 || label_length > FormQuestion::MAX_LABEL_LENGTH {
+            issues.push(ConfigIssue::error(
+                format!("{path}.label"),
+                format!(
+                    "must be between 1 and {} characters",
+                    FormQuestion::MAX_LABEL_LENGTH
+                ),
+            ));
+        }
+        if question
+            .placeholder
+            .as_ref()
+            .is_some_and(|p| p.chars().count() > FormQuestion::MAX_PLACEHOLDER_LENGTH)
+        {
+            issues.push(ConfigIssue::error(
+                format!("{path}.placeholder"),
+                format!(
+                    "must be at most {} characters",
+                    FormQuestion::MAX_PLACEHOLDER_LENGTH
+                ),
+            ));
+        }
+        let lengths = [
+            ("min_length", question.min_length),
+            ("max_length", question.max_length),
+        ];
+        for (field, length) in lengths {
+            if length.is_some_and(|l| l > FormQuestion::MAX_ANSWER_LENGTH) {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.{field}"),
+                    format!("must be at most {}", FormQuestion::MAX_ANSWER_LENGTH),
+                ));
+            }
+        }
+        if question.max_length == Some(0) {
+            issues.push(ConfigIssue::error(
+                format!("{path}.max_length"),
+                "must be at least 1",
+            ));
+        }
+        if question
+            .min_length
+            .zip(question.max_length)
+            .is_some_and(|(min, max)| min > max)
+        {
+            issues.push(ConfigIssue::error(
+                format!("{path}.min_length"),
+                "must not be greater than `max_length`",
+            ));
+        }
+    }
+}
+
+/// Checks that every server has someone who can review its requests, through the whitelist's
+/// `allowed_roles`, the server's `approver_roles` or `whitelist.review` grants.
+fn validate_reviewers(config: &Config, issues: &mut Vec<ConfigIssue>) {
+    let mut scopes: Vec<(String, &WhitelistConfig, Vec<&Server>)> = vec![];
+    if config.guilds.is_empty() {
+        scopes.extend(
+            config
+                .whitelist
+                .iter()
+                .map(|w| ("whitelist".to_string(), w, config.servers.iter().collect())),
+        );
+    }
+    for (i, guild) in config.guilds.iter().enumerate() {
+        let (path, whitelist) = match (&guild.whitelist, &config.whitelist) {
+            (Some(whitelist), _) => (format!("guilds[{i}].whitelist"), whitelist),
+            (None, Some(whitelist)) => ("whitelist".to_string(), whitelist),
+            (None, None) => continue,
+        };
+        scopes.push((path, whitelist, config.servers_for(Some(guild.id))));
+    }
+
+    let mut reported = HashSet::new();
+    for (path, whitelist, servers) in scopes {
+        if whitelist.allow_admin {
+            continue;
+        }
+
+        for server in servers {
+            let has_reviewers = !server.approver_roles(whitelist).is_empty()
+                || permissions::granted_roles(config, Permission::WhitelistReview, Some(server))
+                    .is_some_and(|roles| !roles.is_empty());
+            if has_reviewers || !reported.insert((path.clone(), &server.id)) {
+                continue;
+            }
+
+            issues.push(ConfigIssue::error(
+                format!("{path}.allowed_roles"),
+                format!(
+                    "no roles can review requests for server `{}` and `allow_admin` is disabled, so nobody can approve or deny them",
+                    server.id
+                ),
+            ));
+        }
+    }
+}
+
+fn validate_whitelist(path: &str, whitelist: &WhitelistConfig, issues: &mut Vec<ConfigIssue>) {
+    if whitelist.request_channel == 0 {
+        issues.push(ConfigIssue::error(
+            format!("{path}.request_channel"),
+            "must be a channel ID",
+        ));
+    }
+    for (i, role_id) in whitelist.allowed_roles.iter().enumerate() {
+        if *role_id == 0 {
+            issues.push(ConfigIssue::error(
+                format!("{path}.allowed_roles[{i}]"),
+                "must be a role ID",
+            ));
+        }
+    }
+    for (i, role_id) in whitelist.ping_roles.iter().enumerate() {
+        if *role_id == 0 {
+            issues.push(ConfigIssue::error(
+                format!("{path}.ping_roles[{i}]"),
+                "must be a role ID",
+            ));
+        }
+    }
+    validate_auto_approve(
+        &format!("{path}.auto_approve"),
+        &whitelist.auto_approve,
+        None,
+        issues,
+    );
+
+    let eligibility = &whitelist.eligibility;
+    for (j, role_id) in eligibility.required_roles.iter().flatten().enumerate() {
+        if *role_id == 0 {
+            issues.push(ConfigIssue::error(
+                format!("{path}.eligibility.required_roles[{j}]"),
+                "must be a role ID",
+            ));
+        }
+    }
+    if eligibility
+        .required_roles
+        .as_ref()
+        .is_some_and(|r| r.is_empty())
+    {
+        issues.push(ConfigIssue::error(
+            format!("{path}.eligibility.required_roles"),
+            "no roles are listed, so nobody can request whitelisting",
+        ));
+    }
+    let limits = [
+        ("max_pending_requests", eligibility.max_pending_requests),
+        ("max_accounts_per_user", eligibility.max_accounts_per_user),
+    ];
+    for (field, limit) in limits {
+        if limit == Some(0) {
+            issues.push(ConfigIssue::error(
+                format!("{path}.eligibility.{field}"),
+                "must be at least 1, or unset to disable the limit",
+            ));
+        }
+    }
+    if whitelist.log_channel == Some(0) {
+        issues.push(ConfigIssue::error(
+            format!("{path}.log_channel"),
+            "must be a channel ID",
+        ));
+    }
+    if let Some(reminders) = &whitelist.reminders {
+        if reminders.interval_hours == 0 {
+            issues.push(ConfigIssue::error(
+                format!("{path}.reminders.interval_hours"),
+                "must be at least 1",
+            ));
+        }
+        if reminders.channel == Some(0) {
+            issues.push(ConfigIssue::error(
+                format!("{path}.reminders.channel"),
+                "must be a channel ID",
+            ));
+        }
+    }
+}
+
+fn validate_auto_approve(
+    path: &str,
+    rules: &[AutoApproveRule],
+    server: Option<&Server>,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    for (i, rule) in rules.iter().enumerate() {
+        let path = format!("{path}[{i}]");
+
+        if !rule.has_conditions() {
+            issues.push(ConfigIssue::error(
+                path.clone(),
+                "rule has no conditions, so it would approve every request",
+            ));
+        }
+        for (j, role_id) in rule.roles.iter().flatten().enumerate() {
+            if *role_id == 0 {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.roles[{j}]"),
+                    "must be a role ID",
+                ));
+            }
+        }
+        if server.is_some_and(|s| s.group.is_none()) && rule.whitelisted_in_group {
+            issues.push(ConfigIssue::warning(
+                format!("{path}.whitelisted_in_group"),
+                "the server isn't in a `group`, so this condition never matches",
+            ));
+        }
+    }
+}
+
+fn validate_permissions(
+    path: &str,
+    permissions: &BTreeMap<Permission, Vec<u64>>,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    for (permission, role_ids) in permissions {
+        for (i, role_id) in role_ids.iter().enumerate() {
+            if *role_id == 0 {
+                issues.push(ConfigIssue::error(
+                    format!("{path}.\"{permission}\"[{i}]"),
+                    "must be a role ID",
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigIssue, validate};
+    use crate::models::config::Config;
+
+    const VALID: &str = r#"
+        [whitelist]
+        request_channel = 1
+        allowed_roles = [2]
+        ping_roles = []
+
+        [[servers]]
+        id = "survival"
+        name = "Survival"
+        query_port = 25565
+        rcon_port = 25575
+        rcon_password = "hunter2"
+        container_name = "survival"
+    "#;
+
+    fn config(extra: &str) -> Config {
+        toml::from_str(&format!("{VALID}\n{extra}")).unwrap()
+    }
+
+    fn error_paths(issues: &[ConfigIssue]) -> Vec<&str> {
+        issues
+            .iter()
+            .filter(|issue| issue.is_error())
+            .map(|issue| issue.path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_config() {
+        let issues = validate(&config(""));
+
+        assert!(issues.is_empty(), "unexpected issues: {issues:?}");
+    }
+
+    #[test]
+    fn rejects_unknown_server_references() {
+        let issues = validate(&config(
+            r#"
+            [[guilds]]
+            id = 3
+            servers = ["creative"]
+
+            [[groups]]
+            id = "network"
+            name = "Network"
+            proxy = "velocity"
+            "#,
+        ));
+
+        assert_eq!(
+            error_paths(&issues),
+            ["guilds[0].servers[0]", "groups[0].proxy"]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let issues = validate(&config(
+            r#"
+            [[servers]]
+            id = "survival"
+            name = "Survival 2"
+            query_port = 25566
+            rcon_port = 25576
+            rcon_password = "hunter2"
+            container_name = "survival-2"
+
+            [[guilds]]
+            id = 3
+
+            [[guilds]]
+            id = 3
+
+            [[groups]]
+            id = "survival"
+            name = "Survival network"
+            "#,
+        ));
+
+        assert_eq!(
+            error_paths(&issues),
+            ["guilds[1].id", "servers[1].id", "groups[0].id"]
+        );
+    }
+
+    #[test]
+    fn rejects_missing_channel_and_role_ids() {
+        let mut config = config(
+            r#"
+            [servers.whitelist]
+            whitelist_role = 0
+            ping_roles = [0]
+            "#,
+        );
+        let whitelist = config.whitelist.as_mut().unwrap();
+        whitelist.request_channel = 0;
+        whitelist.allowed_roles.push(0);
+
+        assert_eq!(
+            error_paths(&validate(&config)),
+            [
+                "whitelist.request_channel",
+                "whitelist.allowed_roles[1]",
+                "servers[0].whitelist.whitelist_role",
+                "servers[0].whitelist.ping_roles[0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_required_approvals_of_zero() {
+        let issues = validate(&config(
+            r#"
+            [servers.whitelist]
+            required_approvals = 0
+            "#,
+        ));
+
+        assert_eq!(
+            error_paths(&issues),
+            ["servers[0].whitelist.required_approvals"]
+        );
+    }
+
+    #[test]
+    fn rejects_servers_nobody_can_review() {
+        let mut config = config("");
+        let whitelist = config.whitelist.as_mut().unwrap();
+        whitelist.allow_admin = false;
+        whitelist.allowed_roles.clear();
+
+        assert_eq!(error_paths(&validate(&config)), ["whitelist.allowed_roles"]);
+    }
+
+    #[test]
+    fn accepts_reviewers_from_approver_roles_and_grants() {
+        let mut approvers = config(
+            r#"
+            [servers.whitelist]
+            approver_roles = [4]
+            "#,
+        );
+        let mut granted = config(
+            r#"
+            [servers.permissions]
+            "whitelist.review" = [5]
+            "#,
+        );
+
+        for config in [&mut approvers, &mut granted] {
+            let whitelist = config.whitelist.as_mut().unwrap();
+            whitelist.allow_admin = false;
+            whitelist.allowed_roles.clear();
+
+            let issues = validate(config);
+            assert!(issues.is_empty(), "unexpected issues: {issues:?}");
+        }
+    }
+
+    #[test]
+    fn warns_about_bot_wide_permissions_granted_by_servers() {
+        let issues = validate(&config(
+            r#"
+            [servers.permissions]
+            "bot.manage" = [6]
+            "#,
+        ));
+
+        let paths: Vec<&str> = issues.iter().map(|issue| issue.path.as_str()).collect();
+        assert_eq!(paths, ["servers[0].permissions.\"bot.manage\""]);
+        assert!(!issues[0].is_error());
+    }
+}